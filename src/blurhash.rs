@@ -0,0 +1,159 @@
+use std::f32::consts::PI;
+
+use image::{DynamicImage, Rgb, RgbImage};
+
+use crate::errors::Errors;
+
+const CHARACTERS: &[u8; 83] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+const MAX_SAMPLE_SIZE: u32 = 64;
+
+pub fn blurhash_encode(
+    image: &DynamicImage,
+    x_components: u32,
+    y_components: u32,
+) -> Result<String, Errors> {
+    if !(1..=9).contains(&x_components) || !(1..=9).contains(&y_components) {
+        return Err(Errors::InvalidBlurhashComponents);
+    }
+    let image = if image.width() > MAX_SAMPLE_SIZE || image.height() > MAX_SAMPLE_SIZE {
+        image
+            .thumbnail(MAX_SAMPLE_SIZE, MAX_SAMPLE_SIZE)
+            .into_rgb8()
+    } else {
+        image.to_rgb8()
+    };
+    let (w, h) = image.dimensions();
+
+    let mut factors = Vec::with_capacity((x_components * y_components) as usize);
+    for j in 0..y_components {
+        for i in 0..x_components {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0f32; 3];
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let basis = (PI * i as f32 * x as f32 / w as f32).cos()
+                    * (PI * j as f32 * y as f32 / h as f32).cos();
+                (0..3).for_each(|c| factor[c] += basis * srgb_to_linear(pixel[c]));
+            }
+            let scale = normalisation / (w * h).max(1) as f32;
+            factors.push(factor.map(|f| f * scale));
+        }
+    }
+
+    let (dc, ac) = factors.split_first().unwrap();
+    let mut hash = encode_base83((x_components - 1) + (y_components - 1) * 9, 1);
+
+    let maximum_value = if ac.is_empty() {
+        hash.push_str(&encode_base83(0, 1));
+        1.0
+    } else {
+        let actual_max = ac
+            .iter()
+            .flat_map(|f| f.iter())
+            .fold(0.0f32, |max, v| max.max(v.abs()));
+        let quantised_max = ((actual_max * 166.0 - 0.5).floor() as i32).clamp(0, 82) as u32;
+        hash.push_str(&encode_base83(quantised_max, 1));
+        (quantised_max + 1) as f32 / 166.0
+    };
+
+    hash.push_str(&encode_base83(encode_dc(dc), 4));
+    for factor in ac {
+        hash.push_str(&encode_base83(encode_ac(factor, maximum_value), 2));
+    }
+    Ok(hash)
+}
+
+pub fn blurhash_decode(hash: &str, w: u32, h: u32) -> Result<DynamicImage, Errors> {
+    if hash.len() < 6 || !hash.is_ascii() {
+        return Err(Errors::InvalidBlurhash);
+    }
+    let size_flag = decode_base83(&hash[0..1])?;
+    let x_components = size_flag % 9 + 1;
+    let y_components = size_flag / 9 + 1;
+    if hash.len() != (4 + 2 * x_components * y_components) as usize {
+        return Err(Errors::InvalidBlurhash);
+    }
+    let maximum_value = (decode_base83(&hash[1..2])? + 1) as f32 / 166.0;
+
+    let mut colors = vec![decode_dc(decode_base83(&hash[2..6])?)];
+    for i in 1..(x_components * y_components) as usize {
+        let value = decode_base83(&hash[4 + i * 2..6 + i * 2])?;
+        colors.push(decode_ac(value, maximum_value));
+    }
+
+    let image = RgbImage::from_fn(w, h, |x, y| {
+        let mut pixel = [0.0f32; 3];
+        for j in 0..y_components {
+            for i in 0..x_components {
+                let basis = (PI * x as f32 * i as f32 / w as f32).cos()
+                    * (PI * y as f32 * j as f32 / h as f32).cos();
+                let color = colors[(i + j * x_components) as usize];
+                (0..3).for_each(|c| pixel[c] += color[c] * basis);
+            }
+        }
+        Rgb(pixel.map(linear_to_srgb))
+    });
+    Ok(DynamicImage::ImageRgb8(image))
+}
+
+fn encode_base83(value: u32, length: u32) -> String {
+    (1..=length)
+        .map(|i| {
+            let digit = (value / 83u32.pow(length - i)) % 83;
+            CHARACTERS[digit as usize] as char
+        })
+        .collect()
+}
+
+fn decode_base83(text: &str) -> Result<u32, Errors> {
+    text.bytes().try_fold(0, |value, c| {
+        let digit = CHARACTERS
+            .iter()
+            .position(|&d| d == c)
+            .ok_or(Errors::InvalidBlurhash)?;
+        Ok(value * 83 + digit as u32)
+    })
+}
+
+fn encode_dc(value: &[f32; 3]) -> u32 {
+    let [r, g, b] = value.map(|v| linear_to_srgb(v) as u32);
+    (r << 16) + (g << 8) + b
+}
+
+fn decode_dc(value: u32) -> [f32; 3] {
+    [value >> 16, (value >> 8) & 255, value & 255].map(|v| srgb_to_linear(v as u8))
+}
+
+fn encode_ac(value: &[f32; 3], maximum_value: f32) -> u32 {
+    let [r, g, b] = value.map(|v| {
+        ((sign_pow(v / maximum_value, 0.5) * 9.0 + 9.5).floor() as i32).clamp(0, 18) as u32
+    });
+    r * 19 * 19 + g * 19 + b
+}
+
+fn decode_ac(value: u32, maximum_value: f32) -> [f32; 3] {
+    [value / (19 * 19), (value / 19) % 19, value % 19]
+        .map(|q| sign_pow((q as f32 - 9.0) / 9.0, 2.0) * maximum_value)
+}
+
+fn sign_pow(value: f32, exp: f32) -> f32 {
+    value.abs().powf(exp).copysign(value)
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let v = value as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    if v <= 0.003_130_8 {
+        (v * 12.92 * 255.0 + 0.5) as u8
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u8
+    }
+}
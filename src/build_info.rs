@@ -3,7 +3,7 @@ include!(concat!(env!("OUT_DIR"), "/built.rs"));
 pub fn version_str() -> String {
     let git_commit = match GIT_COMMIT_HASH {
         Some(v) => &v[..9],
-        None => ("Unknown commit"),
+        None => "Unknown commit",
     };
     let debug = if DEBUG { " (debug)" } else { "" };
     format!(
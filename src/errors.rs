@@ -4,8 +4,26 @@ pub enum Errors {
     InvalidImageType,
     InvalidResizeFilter,
     InputImageAlreadyUsed,
+    RegionSizeChanged,
+    Cancelled,
+    InvalidNinePatch,
+    InvalidQuad,
+    InvalidDitherParameters,
+    InvalidDimensions,
+    InvalidSegments,
+    InvalidOutputFormat,
+    InvalidGamma,
+    InvalidKernel,
     IOError(std::io::Error),
     ImageError(image::ImageError),
+    #[cfg(feature = "blurhash")]
+    InvalidBlurhash,
+    #[cfg(feature = "blurhash")]
+    InvalidBlurhashComponents,
+    #[cfg(feature = "chrono")]
+    InvalidTimestamp,
+    #[cfg(feature = "webp")]
+    WebpEncodeError(webp::WebPEncodingError),
     #[cfg(feature = "base64")]
     Base64DecodeError(base64::DecodeError),
     #[cfg(feature = "reqwest")]
@@ -0,0 +1,136 @@
+const EXIF_IFD_POINTER: u16 = 0x8769;
+
+const TAGS: [(&str, u16); 10] = [
+    ("Make", 0x010F),
+    ("Model", 0x0110),
+    ("DateTime", 0x0132),
+    ("ExposureTime", 0x829A),
+    ("FNumber", 0x829D),
+    ("ISOSpeedRatings", 0x8827),
+    ("DateTimeOriginal", 0x9003),
+    ("FocalLength", 0x920A),
+    ("LensMake", 0xA433),
+    ("LensModel", 0xA434),
+];
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(0..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+        Some(Self {
+            data,
+            little_endian,
+        })
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = [*self.data.get(offset)?, *self.data.get(offset + 1)?];
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn find(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16(ifd)? as usize;
+        (0..count)
+            .map(|i| ifd + 2 + i * 12)
+            .find(|&entry| self.u16(entry) == Some(tag))
+    }
+
+    fn format(&self, entry: usize, tag: u16) -> Option<String> {
+        let kind = self.u16(entry + 2)?;
+        let count = self.u32(entry + 4)? as usize;
+        let value_offset = |size: usize| {
+            if size * count <= 4 {
+                Some(entry + 8)
+            } else {
+                self.u32(entry + 8).map(|o| o as usize)
+            }
+        };
+        match kind {
+            2 => {
+                let start = value_offset(1)?;
+                let text = self.data.get(start..start + count)?;
+                let text = text.split(|&b| b == 0).next().unwrap_or_default();
+                Some(String::from_utf8_lossy(text).trim().to_string())
+            }
+            3 => self.u16(value_offset(2)?).map(|v| v.to_string()),
+            4 => self.u32(value_offset(4)?).map(|v| v.to_string()),
+            5 => {
+                let start = value_offset(8)?;
+                let (numerator, denominator) = (self.u32(start)?, self.u32(start + 4)?);
+                if denominator == 0 {
+                    return None;
+                }
+                let value = numerator as f64 / denominator as f64;
+                Some(match tag {
+                    0x829A if value < 1.0 => format!("1/{}s", (1.0 / value).round()),
+                    0x829A => format!("{value}s"),
+                    0x829D => format!("f/{value:.1}"),
+                    0x920A => format!("{value:.0}mm"),
+                    _ => format!("{value}"),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+fn tiff_data(bytes: &[u8]) -> Option<&[u8]> {
+    if bytes.get(0..2)? != [0xFF, 0xD8] {
+        return Some(bytes);
+    }
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let segment = bytes.get(pos + 4..pos + 2 + length)?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            return Some(&segment[6..]);
+        }
+        pos += 2 + length;
+    }
+    None
+}
+
+/// Returns the raw EXIF (TIFF) block of encoded JPEG or TIFF bytes.
+pub fn extract_exif(bytes: &[u8]) -> Option<Vec<u8>> {
+    let data = tiff_data(bytes)?;
+    Tiff::new(data)?;
+    Some(data.to_vec())
+}
+
+/// Reads a named EXIF field (e.g. `"Model"`, `"DateTimeOriginal"`,
+/// `"FNumber"`) from encoded JPEG or TIFF bytes.
+pub fn read_exif_field(bytes: &[u8], name: &str) -> Option<String> {
+    let tag = TAGS.iter().find(|(n, _)| *n == name)?.1;
+    let tiff = Tiff::new(tiff_data(bytes)?)?;
+    let ifd0 = tiff.u32(4)? as usize;
+    let entry = tiff.find(ifd0, tag).or_else(|| {
+        let pointer = tiff.find(ifd0, EXIF_IFD_POINTER)?;
+        tiff.find(tiff.u32(pointer + 8)? as usize, tag)
+    })?;
+    tiff.format(entry, tag)
+}
@@ -1,20 +1,51 @@
-use std::{default::Default, fs, io::Cursor};
+use std::{
+    collections::HashSet,
+    default::Default,
+    fs,
+    io::{self, Cursor, Read, Write},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use conv::ValueInto;
-use image::imageops::FilterType;
 pub use image::{
-    imageops, io::Reader, DynamicImage, GenericImage, GenericImageView, ImageOutputFormat, Pixel,
-    Rgb, RgbImage, Rgba,
+    imageops, io::Reader, DynamicImage, GenericImage, GenericImageView, GrayImage,
+    ImageOutputFormat, Luma, LumaA, Pixel, Rgb, RgbImage, Rgba, RgbaImage,
+};
+use image::{imageops::FilterType, ColorType, ImageBuffer};
+use imageproc::{
+    contrast::equalize_histogram,
+    distance_transform::euclidean_squared_distance_transform,
+    drawing::{
+        draw_filled_circle_mut, draw_filled_ellipse_mut, draw_filled_rect_mut,
+        draw_hollow_ellipse_mut, draw_hollow_rect_mut, draw_line_segment_mut,
+    },
+    filter::{median_filter, Kernel},
+    geometric_transformations::{warp_into, warp_with, Interpolation, Projection},
+    gradients::{horizontal_sobel, sobel_gradients, vertical_sobel},
+    rect::Rect,
 };
 pub use imageproc::{definitions::Clamp, drawing::draw_text_mut};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 pub use rusttype::{point, Font, Scale};
 #[cfg(feature = "serde")]
 use serde::Deserialize;
 
+#[cfg(feature = "blurhash")]
+pub mod blurhash;
 pub mod build_info;
 pub mod errors;
+#[cfg(feature = "exif")]
+pub mod exif;
+pub mod random;
 
+#[cfg(feature = "blurhash")]
+pub use crate::blurhash::{blurhash_decode, blurhash_encode};
 pub use crate::errors::Errors;
+#[cfg(feature = "exif")]
+pub use crate::exif::read_exif_field;
+pub use crate::random::set_global_seed;
 
 #[cfg_attr(
     feature = "serde",
@@ -29,6 +60,268 @@ pub enum ResizeMode {
     Fill,
 }
 
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[derive(Default)]
+pub enum Prefer {
+    Crop,
+    Pad,
+    #[default]
+    Auto,
+}
+
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[derive(Clone, Copy, Default)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    #[default]
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    fn position(&self, outer: (u32, u32), inner: (u32, u32)) -> (i64, i64) {
+        let free_x = outer.0 as i64 - inner.0 as i64;
+        let free_y = outer.1 as i64 - inner.1 as i64;
+        let x = match self {
+            Self::TopLeft | Self::Left | Self::BottomLeft => 0,
+            Self::Top | Self::Center | Self::Bottom => free_x / 2,
+            Self::TopRight | Self::Right | Self::BottomRight => free_x,
+        };
+        let y = match self {
+            Self::TopLeft | Self::Top | Self::TopRight => 0,
+            Self::Left | Self::Center | Self::Right => free_y / 2,
+            Self::BottomLeft | Self::Bottom | Self::BottomRight => free_y,
+        };
+        (x, y)
+    }
+}
+
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum PaletteName {
+    WebSafe,
+    Cga,
+    GameBoy,
+}
+
+const CGA_PALETTE: [u32; 16] = [
+    0x000000, 0x0000AA, 0x00AA00, 0x00AAAA, 0xAA0000, 0xAA00AA, 0xAA5500, 0xAAAAAA, 0x555555,
+    0x5555FF, 0x55FF55, 0x55FFFF, 0xFF5555, 0xFF55FF, 0xFFFF55, 0xFFFFFF,
+];
+const GAME_BOY_PALETTE: [u32; 4] = [0x0F380F, 0x306230, 0x8BAC0F, 0x9BBC0F];
+
+impl PaletteName {
+    pub fn colors(&self) -> Vec<Rgb<u8>> {
+        let from_hex = |&hex: &u32| Rgb([(hex >> 16) as u8, (hex >> 8) as u8, hex as u8]);
+        match self {
+            Self::WebSafe => (0..216)
+                .map(|i| Rgb([i / 36, (i / 6) % 6, i % 6].map(|v| (v * 51) as u8)))
+                .collect(),
+            Self::Cga => CGA_PALETTE.iter().map(from_hex).collect(),
+            Self::GameBoy => GAME_BOY_PALETTE.iter().map(from_hex).collect(),
+        }
+    }
+}
+
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[derive(Clone, Copy)]
+pub enum ParticleKind {
+    Snow,
+    Confetti,
+}
+
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[derive(Clone, Copy)]
+pub enum Axis {
+    /// Left and right halves.
+    Horizontal,
+    /// Top and bottom halves.
+    Vertical,
+}
+
+/// The left half for `Axis::Horizontal`, or the top half for `Axis::Vertical`,
+/// is the `Start`.
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[derive(Clone, Copy)]
+pub enum Side {
+    Start,
+    End,
+}
+
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[derive(Clone, Copy)]
+pub enum Direction {
+    TopToBottom,
+    BottomToTop,
+    LeftToRight,
+    RightToLeft,
+}
+
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[derive(Clone, Copy, Default)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+impl BlendMode {
+    /// Blends normalized RGB triples. The hue, saturation, color and
+    /// luminosity modes follow the non-separable formulas of the W3C
+    /// compositing spec.
+    fn blend(&self, base: [f32; 3], layer: [f32; 3]) -> [f32; 3] {
+        let separable = |f: fn(f32, f32) -> f32| [0, 1, 2].map(|i| f(base[i], layer[i]));
+        match self {
+            Self::Normal => layer,
+            Self::Multiply => separable(|b, l| b * l),
+            Self::Screen => separable(|b, l| 1.0 - (1.0 - b) * (1.0 - l)),
+            Self::Overlay => separable(|b, l| {
+                if b < 0.5 {
+                    2.0 * b * l
+                } else {
+                    1.0 - 2.0 * (1.0 - b) * (1.0 - l)
+                }
+            }),
+            Self::Darken => separable(f32::min),
+            Self::Lighten => separable(f32::max),
+            Self::Hue => set_lum(set_sat(layer, sat(base)), lum(base)),
+            Self::Saturation => set_lum(set_sat(base, sat(layer)), lum(base)),
+            Self::Color => set_lum(layer, lum(base)),
+            Self::Luminosity => set_lum(base, lum(layer)),
+        }
+    }
+}
+
+fn lum(color: [f32; 3]) -> f32 {
+    0.3 * color[0] + 0.59 * color[1] + 0.11 * color[2]
+}
+
+fn sat(color: [f32; 3]) -> f32 {
+    color.iter().copied().fold(f32::MIN, f32::max) - color.iter().copied().fold(f32::MAX, f32::min)
+}
+
+fn set_lum(color: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(color);
+    let color = color.map(|c| c + d);
+    let l = lum(color);
+    let min = color.iter().copied().fold(f32::MAX, f32::min);
+    let max = color.iter().copied().fold(f32::MIN, f32::max);
+    color.map(|mut c| {
+        if min < 0.0 {
+            c = l + (c - l) * l / (l - min);
+        }
+        if max > 1.0 {
+            c = l + (c - l) * (1.0 - l) / (max - l);
+        }
+        c
+    })
+}
+
+fn set_sat(color: [f32; 3], s: f32) -> [f32; 3] {
+    let min = color.iter().copied().fold(f32::MAX, f32::min);
+    let range = sat(color);
+    if range <= 0.0 {
+        return [0.0; 3];
+    }
+    color.map(|c| (c - min) * s / range)
+}
+
+impl ResizeMode {
+    fn resize(&self, image: &DynamicImage, w: u32, h: u32, filter: FilterType) -> DynamicImage {
+        match self {
+            Self::Fit => image.resize(w, h, filter),
+            Self::Exact => image.resize_exact(w, h, filter),
+            Self::Fill => image.resize_to_fill(w, h, filter),
+        }
+    }
+}
+
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "PascalCase")
+)]
+#[derive(Clone, Copy)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Gaussian => FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+impl FromStr for ResizeFilter {
+    type Err = Errors;
+
+    fn from_str(filter: &str) -> Result<Self, Self::Err> {
+        match filter {
+            "Nearest" => Ok(Self::Nearest),
+            "Triangle" => Ok(Self::Triangle),
+            "CatmullRom" => Ok(Self::CatmullRom),
+            "Gaussian" => Ok(Self::Gaussian),
+            "Lanczos3" => Ok(Self::Lanczos3),
+            _ => Err(Errors::InvalidResizeFilter),
+        }
+    }
+}
+
 #[cfg_attr(
     feature = "serde",
     derive(Deserialize),
@@ -49,6 +342,28 @@ impl ImageInput {
         }
         Ok(image)
     }
+
+    /// Like `get_image`, but also returns the raw EXIF block when the image
+    /// was decoded from encoded bytes that carry one.
+    fn get_image_with_exif(self) -> Result<(DynamicImage, Option<Vec<u8>>), Errors> {
+        #[cfg(feature = "exif")]
+        let (mut image, exif) = self.image_input_type.get_image_with_exif()?;
+        #[cfg(not(feature = "exif"))]
+        let (mut image, exif) = (self.image_input_type.get_image()?, None);
+        for operation in self.operations.into_iter() {
+            image = operation.apply(image)?;
+        }
+        Ok((image, exif))
+    }
+}
+
+impl From<ImageInputType> for ImageInput {
+    fn from(image_input_type: ImageInputType) -> Self {
+        Self {
+            image_input_type,
+            operations: Vec::new(),
+        }
+    }
 }
 
 #[cfg_attr(
@@ -78,6 +393,10 @@ pub enum ImageInputType {
     Base64(String),
     #[cfg(feature = "reqwest")]
     Url(String),
+    Identicon {
+        seed: String,
+        size: u32,
+    },
 }
 
 macro_rules! new_image{
@@ -119,8 +438,24 @@ impl ImageInputType {
             Self::Url(url) => Ok(image::load_from_memory(
                 &reqwest::blocking::get(url)?.bytes()?,
             )?),
+            Self::Identicon { seed, size } => Ok(DynamicImage::ImageRgb8(identicon(&seed, size))),
         }
     }
+
+    #[cfg(feature = "exif")]
+    fn get_image_with_exif(self) -> Result<(DynamicImage, Option<Vec<u8>>), Errors> {
+        let bytes = match self {
+            Self::Filename(name) => load_file(&name)?,
+            Self::Bytes(bytes) => bytes,
+            #[cfg(feature = "base64")]
+            Self::Base64(encoded) => base64::decode(encoded)?,
+            #[cfg(feature = "reqwest")]
+            Self::Url(url) => reqwest::blocking::get(url)?.bytes()?.to_vec(),
+            other => return Ok((other.get_image()?, None)),
+        };
+        let exif = exif::extract_exif(&bytes);
+        Ok((image::load_from_memory(&bytes)?, exif))
+    }
 }
 
 #[cfg_attr(
@@ -166,6 +501,8 @@ impl FontInput {
 pub struct ImageOperator {
     pub image_input: Option<ImageInput>,
     pub operations: Vec<ImageOperation>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_output: Option<(u32, u32)>,
     #[cfg_attr(feature = "serde", serde(skip_deserializing))]
     image: Option<DynamicImage>,
 }
@@ -175,24 +512,102 @@ impl ImageOperator {
         Self {
             image_input: Some(image_input),
             operations,
+            max_output: None,
             image: None,
         }
     }
 
+    pub fn with_max_output(self, w: u32, h: u32) -> Self {
+        Self {
+            max_output: Some((w, h)),
+            ..self
+        }
+    }
+
     pub fn apply_all_operations(self) -> Result<Self, Errors> {
-        let mut image = self
+        self.apply_all_operations_cancellable(&AtomicBool::new(false))
+    }
+
+    /// Like `apply_all_operations`, but returns `Errors::Cancelled` once
+    /// `cancel` is set. The flag is checked between top-level operations
+    /// only, so a running operation, including every op nested in a
+    /// `Region`, always finishes first.
+    pub fn apply_all_operations_cancellable(self, cancel: &AtomicBool) -> Result<Self, Errors> {
+        let (image, exif) = self
             .image_input
             .ok_or(Errors::InputImageAlreadyUsed)?
-            .get_image()?;
-        for op in self.operations.into_iter() {
-            image = op.apply(image)?;
+            .get_image_with_exif()?;
+        Self::run(
+            image,
+            exif.as_deref(),
+            self.operations,
+            self.max_output,
+            cancel,
+        )
+    }
+
+    fn run(
+        mut image: DynamicImage,
+        exif: Option<&[u8]>,
+        operations: Vec<ImageOperation>,
+        max_output: Option<(u32, u32)>,
+        cancel: &AtomicBool,
+    ) -> Result<Self, Errors> {
+        for op in operations.into_iter() {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(Errors::Cancelled);
+            }
+            image = op.apply_with_exif(image, exif)?;
+        }
+        if cancel.load(Ordering::Relaxed) {
+            return Err(Errors::Cancelled);
+        }
+        if let Some((w, h)) = max_output {
+            image = cap_dimensions(image, w, h);
         }
         Ok(Self {
             image_input: None,
             operations: Vec::new(),
+            max_output: None,
             image: Some(image),
         })
     }
+    /// Runs the pipeline on a copy of the input downscaled to fit `max_dim`,
+    /// with pixel-valued operation parameters scaled to match. Layer images
+    /// (Overlay, Tile, FramedBy, ...) are not rescaled and font scales are
+    /// approximate, so previews of such ops only approximate the
+    /// full-resolution result.
+    pub fn apply_all_operations_preview(self, max_dim: u32) -> Result<Self, Errors> {
+        let (image, exif) = self
+            .image_input
+            .ok_or(Errors::InputImageAlreadyUsed)?
+            .get_image_with_exif()?;
+        let cancel = AtomicBool::new(false);
+        let largest = image.width().max(image.height());
+        if largest <= max_dim {
+            return Self::run(
+                image,
+                exif.as_deref(),
+                self.operations,
+                self.max_output,
+                &cancel,
+            );
+        }
+
+        let factor = max_dim as f32 / largest as f32;
+        let scale = |v: u32| ((v as f32 * factor).round() as u32).max(1);
+        Self::run(
+            image.thumbnail(max_dim, max_dim),
+            exif.as_deref(),
+            self.operations
+                .into_iter()
+                .map(|op| op.scaled(factor))
+                .collect(),
+            self.max_output.map(|(w, h)| (scale(w), scale(h))),
+            &cancel,
+        )
+    }
+
     pub fn get_image(self) -> Option<DynamicImage> {
         self.image
     }
@@ -213,6 +628,48 @@ impl ScaleTuple {
     }
 }
 
+/// Text that scrolls right-to-left across a sequence of frames. There is no
+/// multi-frame pipeline yet, so `frames` renders each frame from the same base
+/// image; the caller encodes them (e.g. with `image::codecs::gif::GifEncoder`).
+#[cfg_attr(
+    feature = "serde",
+    derive(Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub struct AnimatedMarquee {
+    pub text: String,
+    pub font: FontInput,
+    pub scale: ScaleTuple,
+    pub color: [u8; 4],
+    pub y: i32,
+    pub speed: u32,
+}
+
+impl AnimatedMarquee {
+    pub fn frames(self, image: &DynamicImage, count: u32) -> Result<Vec<DynamicImage>, Errors> {
+        let font = self.font.get_font()?;
+        let scale = self.scale.to_scale();
+        let text_width = measure_line_width(&font, &self.text, scale) as i64;
+        let period = image.width() as i64 + text_width;
+        Ok((0..count)
+            .map(|index| {
+                let offset = (index as i64 * self.speed as i64) % period.max(1);
+                let left = image.width() as i64 - offset;
+                let mut frame = image.clone();
+                draw_text(
+                    &mut frame,
+                    Rgba(self.color),
+                    &font,
+                    &self.text,
+                    scale,
+                    &((left + text_width / 2) as i32, self.y),
+                );
+                frame
+            })
+            .collect())
+    }
+}
+
 #[cfg_attr(
     feature = "serde",
     derive(Deserialize),
@@ -228,7 +685,7 @@ pub enum ImageOperation {
     Resize {
         h: u32,
         w: u32,
-        filter: String,
+        filter: ResizeFilter,
         #[cfg_attr(feature = "serde", serde(default))]
         mode: ResizeMode,
     },
@@ -241,6 +698,8 @@ pub enum ImageOperation {
     Overlay {
         layer_image_input: ImageInput,
         coords: (i64, i64),
+        #[cfg_attr(feature = "serde", serde(default))]
+        mode: BlendMode,
     },
     Tile {
         tile_image: ImageInput,
@@ -267,6 +726,7 @@ pub enum ImageOperation {
     },
     Brighten(i32),
     AdjustContrast(f32),
+    Gamma(f32),
     HueRotate(i32),
     Invert,
     Grayscale,
@@ -275,136 +735,3680 @@ pub enum ImageOperation {
     Rotate90,
     Rotate180,
     Rotate270,
-}
-
-impl ImageOperation {
-    fn apply(self, mut image: DynamicImage) -> Result<DynamicImage, Errors> {
-        match self {
-            Self::Thumbnail { h, w, exact } => Ok(if exact {
-                image.thumbnail_exact(w, h)
-            } else {
-                image.thumbnail(w, h)
-            }),
-            Self::Resize { h, w, filter, mode } => {
-                let func = match mode {
-                    ResizeMode::Fit => DynamicImage::resize,
-                    ResizeMode::Exact => DynamicImage::resize_exact,
-                    ResizeMode::Fill => DynamicImage::resize_to_fill,
-                };
-                Ok(func(&image, w, h, filter_from_str(filter)?))
-            }
-            Self::Crop { x, y, w, h } => Ok(image.crop_imm(x, y, w, h)),
-            Self::Overlay {
-                layer_image_input,
-                coords,
-            } => {
-                imageops::overlay(
-                    &mut image,
-                    &layer_image_input.get_image()?,
-                    coords.0,
-                    coords.1,
-                );
-                Ok(image)
-            }
-            Self::Tile { tile_image } => {
-                image::imageops::tile(&mut image, &tile_image.get_image()?);
-                Ok(image)
-            }
-            Self::DrawText {
-                mut text,
-                color,
-                font,
-                scale,
-                mid,
-                max_width,
-            } => {
-                if let Some(width) = max_width {
-                    text = textwrap::fill(&text, width);
-                }
-                let color = Rgba(color);
-                draw_text(
-                    &mut image,
-                    color,
-                    &font.get_font()?,
-                    &text,
-                    scale.to_scale(),
-                    &mid,
-                );
-                Ok(image)
-            }
-            Self::ColorBlend { r, g, b } => {
-                let color = [r, g, b];
-                let h = image.height();
-                let w = image.width();
-
-                (0..w).for_each(|x| {
-                    (0..h).for_each(|y| {
-                        let mut pixel = image.get_pixel(x, y);
-                        (0..3).for_each(|i| {
-                            pixel[i] = pixel[i] / 2 + color[i] / 2;
-                        });
-                        image.put_pixel(x, y, pixel);
-                    })
-                });
-                Ok(image)
-            }
-            Self::Blur { sigma } => Ok(image.blur(sigma)),
-            Self::Unsharpen { sigma, threshold } => {
-                Ok(image::imageops::unsharpen(&image, sigma, threshold).into())
-            }
-            Self::Brighten(value) => Ok(image.brighten(value)),
-            Self::AdjustContrast(value) => Ok(image.adjust_contrast(value)),
-            Self::HueRotate(value) => Ok(image.huerotate(value)),
-            Self::Invert => {
-                image.invert();
-                Ok(image)
-            }
-            Self::Grayscale => Ok(image::imageops::grayscale(&image).into()),
-            Self::FlipHorizontal => Ok(image.fliph()),
-            Self::FlipVertical => Ok(image.flipv()),
-            Self::Rotate90 => Ok(image.rotate90()),
-            Self::Rotate180 => Ok(image.rotate180()),
-            Self::Rotate270 => Ok(image.rotate270()),
-        }
-    }
-}
-
-#[inline]
-pub fn load_file(name: &str) -> Result<Vec<u8>, Errors> {
-    Ok(fs::read(name)?.to_vec())
-}
-
-pub fn load_image_from_file(name: &str) -> Result<DynamicImage, Errors> {
-    let v = load_file(name)?;
-    let c = Cursor::new(v);
-    let img = Reader::new(c).with_guessed_format()?.decode()?;
-    Ok(img)
-}
-
-pub fn load_font_from_file(name: &str) -> Result<Font<'static>, Errors> {
-    Font::try_from_vec(fs::read(name)?.to_vec()).ok_or(Errors::InvalidFont)
-}
-
-pub fn fill_color(color: [u8; 3], size: (u32, u32)) -> RgbImage {
-    let mut img = RgbImage::new(size.0, size.1);
-
+    /// Applies `ops` to the `(x, y, w, h)` sub-image and pastes the result
+    /// back in place. The ops must preserve the region's size, otherwise
+    /// `Errors::RegionSizeChanged` is returned.
+    Region {
+        rect: (u32, u32, u32, u32),
+        ops: Vec<ImageOperation>,
+    },
+    /// Stretches `patch` to `target` and replaces the pipeline image with
+    /// it; the incoming image is discarded. Use `Overlay` afterwards to place
+    /// it over other content.
+    NinePatch {
+        patch: ImageInput,
+        insets: (u32, u32, u32, u32),
+        target: (u32, u32),
+    },
+    /// `corners` are the destination points for the content's top-left,
+    /// top-right, bottom-right and bottom-left corners, in that order.
+    PlaceInQuad {
+        content: ImageInput,
+        corners: [(f32, f32); 4],
+    },
+    AverageColorFill,
+    StackBlur {
+        radius: u32,
+    },
+    TiltShift {
+        focus_y: u32,
+        focus_height: u32,
+        max_sigma: f32,
+    },
+    RemoveRedEye {
+        regions: Vec<(u32, u32, u32, u32)>,
+    },
+    FramedBy {
+        frame: ImageInput,
+        #[cfg_attr(feature = "serde", serde(default))]
+        fit: ResizeMode,
+    },
+    Clahe {
+        tiles: (u32, u32),
+        clip_limit: f32,
+    },
+    OverlayRounded {
+        layer_image_input: ImageInput,
+        coords: (i64, i64),
+        radius: u32,
+    },
+    SelectiveColor {
+        hue: f32,
+        tolerance: f32,
+    },
+    OilPaint {
+        radius: u32,
+        levels: u8,
+    },
+    /// Renders dots on a grid with a pitch of `dot_size` pixels, rotated by
+    /// `angle` degrees. Each dot's area is proportional to the darkness of the
+    /// source at the dot's centre, so solid black fills its cell completely.
+    Halftone {
+        dot_size: u32,
+        angle: f32,
+        color: [u8; 4],
+    },
+    OrderedDither {
+        levels: u8,
+        matrix: u8,
+    },
+    PaletteStrip {
+        k: usize,
+        position: Anchor,
+        thickness: u32,
+    },
+    FitAspect {
+        ratio_w: u32,
+        ratio_h: u32,
+        pad_color: [u8; 4],
+        #[cfg_attr(feature = "serde", serde(default))]
+        prefer: Prefer,
+    },
+    InvertAlpha,
+    FadeEdges {
+        width: u32,
+    },
+    Scanlines {
+        intensity: f32,
+        spacing: u32,
+    },
+    PencilSketch {
+        strength: f32,
+    },
+    DrawTextWave {
+        text: String,
+        font: FontInput,
+        scale: ScaleTuple,
+        color: [u8; 4],
+        mid: (i32, i32),
+        amplitude: f32,
+        wavelength: f32,
+    },
+    DisplacementMap {
+        map: ImageInput,
+        scale: f32,
+    },
+    Bloom {
+        threshold: u8,
+        sigma: f32,
+        intensity: f32,
+    },
+    ProgressBar {
+        rect: (u32, u32, u32, u32),
+        progress: f32,
+        fill: [u8; 4],
+        bg: [u8; 4],
+        #[cfg_attr(feature = "serde", serde(default))]
+        rounded: bool,
+    },
+    Orton {
+        blur_sigma: f32,
+        brightness: f32,
+        blend: f32,
+    },
+    NamedPalette {
+        palette: PaletteName,
+        #[cfg_attr(feature = "serde", serde(default))]
+        dither: bool,
+    },
+    SeamlessClone {
+        source: ImageInput,
+        mask: ImageInput,
+        coords: (i64, i64),
+    },
+    FrostedGlass {
+        radius: u32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        seed: Option<u64>,
+    },
+    /// Stretches contrast so the darkest and brightest 0.5% of pixels clip to
+    /// black and white, boosts saturation by 10%, then applies an unsharp mask
+    /// with sigma 0.8 and threshold 3. Alpha is left untouched.
+    AutoEnhance,
+    DodgeBurn {
+        center: (i32, i32),
+        radius: f32,
+        amount: f32,
+    },
+    Particles {
+        kind: ParticleKind,
+        count: u32,
+        seed: u64,
+        #[cfg_attr(feature = "serde", serde(default))]
+        frame: u32,
+    },
+    Sepia,
+    /// Mirrors the wedge between 0 and `360 / segments` degrees around
+    /// `center` to fill the full circle. Samples that fall outside the source
+    /// are left transparent.
+    Kaleidoscope {
+        segments: u32,
+        center: (i32, i32),
+    },
+    RotateAngle {
+        degrees: f32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        background: [u8; 4],
+    },
+    RotateCrop {
+        degrees: f32,
+    },
+    /// Unsharp-masks only pixels whose Sobel gradient magnitude, scaled to
+    /// `0.0..=1.0`, exceeds `edge_threshold`.
+    EdgeSharpen {
+        amount: f32,
+        edge_threshold: f32,
+    },
+    AdjustSaturation(f32),
+    /// Fits the image within `w`×`h`, first blurring with
+    /// `sigma = (factor - 1) / 2` where `factor` is the downscale ratio, so
+    /// large reductions don't alias. No blur is applied when not shrinking.
+    ThumbnailSmooth {
+        w: u32,
+        h: u32,
+    },
+    /// Pixels brighter than `value` become white and the rest black, with
+    /// `value == 0` mapping everything to white. `invert` swaps the result.
+    Threshold {
+        value: u8,
+        #[cfg_attr(feature = "serde", serde(default))]
+        invert: bool,
+    },
+    Grid {
+        spacing: u32,
+        color: [u8; 4],
+        #[cfg_attr(feature = "serde", serde(default))]
+        labels: bool,
+        #[cfg_attr(feature = "serde", serde(default))]
+        font: Option<FontInput>,
+    },
+    GraduatedFilter {
+        direction: Direction,
+        from_ev: f32,
+        to_ev: f32,
+    },
+    Opacity(f32),
+    /// Draws the requested EXIF fields, space separated, at `anchor`. The
+    /// fields come from the EXIF block of the `ImageOperator` input, so
+    /// nothing is drawn when the input was not decoded from encoded bytes
+    /// (e.g. `DynamicImage`) or when the op runs outside the top-level
+    /// pipeline, such as inside `Region`.
+    #[cfg(feature = "exif")]
+    BurnExif {
+        fields: Vec<String>,
+        font: FontInput,
+        scale: ScaleTuple,
+        color: [u8; 4],
+        #[cfg_attr(feature = "serde", serde(default))]
+        anchor: Anchor,
+    },
+    /// Replaces the alpha channel with the mask's luminance, resized to fit:
+    /// white is opaque and black fully transparent.
+    ApplyAlphaMask {
+        mask: ImageInput,
+    },
+    DrawRect {
+        x: i32,
+        y: i32,
+        w: u32,
+        h: u32,
+        color: [u8; 4],
+        #[cfg_attr(feature = "serde", serde(default))]
+        filled: bool,
+    },
+    DrawLine {
+        from: (f32, f32),
+        to: (f32, f32),
+        color: [u8; 4],
+        thickness: u32,
+    },
+    TileRandom {
+        tile_image: ImageInput,
+        seed: u64,
+    },
+    DrawCircle {
+        cx: i32,
+        cy: i32,
+        radius: i32,
+        color: [u8; 4],
+        #[cfg_attr(feature = "serde", serde(default))]
+        filled: bool,
+    },
+    DrawEllipse {
+        cx: i32,
+        cy: i32,
+        rx: i32,
+        ry: i32,
+        color: [u8; 4],
+        #[cfg_attr(feature = "serde", serde(default))]
+        filled: bool,
+    },
+    /// Treats luminance as height and encodes the Sobel-derived surface normal
+    /// as RGB using the OpenGL convention: +X right (red), +Y up (green) and
+    /// +Z out of the surface (blue). Higher `strength` gives steeper normals.
+    NormalMap {
+        strength: f32,
+    },
+    /// Crops away edge rows and columns whose pixels are all within
+    /// `tolerance` (per channel) of `background`, or of the top-left pixel
+    /// when unset. A fully uniform image is reduced to its top-left pixel.
+    Trim {
+        #[cfg_attr(feature = "serde", serde(default))]
+        tolerance: u8,
+        #[cfg_attr(feature = "serde", serde(default))]
+        background: Option<[u8; 4]>,
+    },
+    Pad {
+        top: u32,
+        right: u32,
+        bottom: u32,
+        left: u32,
+        color: [u8; 4],
+    },
+    /// Posterizes each channel to `levels` (at least 2) and draws black where
+    /// the luminance Sobel magnitude, scaled to `0.0..=1.0`, exceeds
+    /// `edge_threshold`. Edges come from luminance for both color and
+    /// grayscale input, and grayscale input stays gray apart from the outlines.
+    Cartoon {
+        levels: u8,
+        edge_threshold: f32,
+    },
+    /// Projects the image onto a cylinder whose horizontal field of view is
+    /// `fov` degrees (clamped below 180), sampling bilinearly. Samples that
+    /// fall outside the source are fully transparent, so the result is RGBA.
+    CylindricalProject {
+        fov: f32,
+    },
+    /// Makes the corners outside `radius` transparent with an antialiased
+    /// edge. The radius is clamped to half the smaller side.
+    RoundCorners {
+        radius: u32,
+    },
+    /// Makes everything outside the largest centered circle transparent.
+    CircleCrop,
+    /// Replaces each `block_size` square with its mean color, optionally only
+    /// inside the `(x, y, w, h)` region. Blocks start at the region's corner.
+    Pixelate {
+        block_size: u32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        region: Option<(u32, u32, u32, u32)>,
+    },
+    /// Non-local means denoising: each pixel becomes the average of pixels in
+    /// the surrounding `search` radius, weighted by how closely the
+    /// `template`-radius patches around them match. `h` is the filter
+    /// strength in 0..=255 units; larger values smooth more. Alpha is kept.
+    DenoiseNLM {
+        h: f32,
+        template: u32,
+        search: u32,
+    },
+    /// Darkens towards the corners with a smoothstep falloff that begins at
+    /// `radius` (a fraction of the half-diagonal) and reaches `1 - strength`
+    /// brightness at the corners. Alpha and the pixel format are preserved.
+    Vignette {
+        strength: f32,
+        radius: f32,
+    },
+    /// Draws the current UTC time, or `time` (Unix seconds) when set,
+    /// formatted with chrono's strftime-style `format`, at `anchor`.
+    #[cfg(feature = "chrono")]
+    Timestamp {
+        format: String,
+        font: FontInput,
+        scale: ScaleTuple,
+        color: [u8; 4],
+        #[cfg_attr(feature = "serde", serde(default))]
+        anchor: Anchor,
+        #[cfg_attr(feature = "serde", serde(default))]
+        time: Option<i64>,
+    },
+    /// Snaps each color channel to `levels` evenly spaced values; fewer than
+    /// two levels are treated as two.
+    Posterize {
+        levels: u8,
+    },
+    /// Inverts color channel values above `threshold`, with `threshold == 0`
+    /// inverting everything like `Invert`. Alpha is untouched.
+    Solarize {
+        threshold: u8,
+    },
+    /// Mirrors the `keep` half of the image onto the other half along
+    /// `axis`. With an odd size the middle row or column is its own mirror
+    /// and is left as is.
+    MirrorHalf {
+        axis: Axis,
+        keep: Side,
+    },
+    /// Correlates the image with a row-major `width`×`height` kernel, dividing
+    /// by `divisor` (the kernel sum by default, or 1 when that is 0) and then
+    /// adding `bias`. Edges are clamp-extended and alpha is untouched.
+    Convolve {
+        kernel: Vec<f32>,
+        width: u32,
+        height: u32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        divisor: Option<f32>,
+        #[cfg_attr(feature = "serde", serde(default))]
+        bias: f32,
+    },
+    /// Sobel gradient magnitude of the luminance as an 8-bit grayscale image.
+    /// Magnitudes saturate at 255 unless `normalize` stretches the strongest
+    /// edge to 255.
+    EdgeDetect {
+        #[cfg_attr(feature = "serde", serde(default))]
+        normalize: bool,
+    },
+    /// Applies the classic emboss kernel scaled by `strength` and offset by
+    /// 128 so flat areas turn mid gray. Luminance is embossed into a grayscale
+    /// result unless `per_channel` embosses each color channel. Any alpha
+    /// channel becomes fully opaque.
+    Emboss {
+        strength: f32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        per_channel: bool,
+    },
+    /// Bulges (positive `strength`) or pinches (negative) the pixels within
+    /// `radius` of `center`, sampling bilinearly. Each destination at
+    /// normalized distance `r` samples the source at `r^(1 + strength)`,
+    /// with the exponent kept at or above 0.1.
+    Spherize {
+        center: (i32, i32),
+        radius: f32,
+        strength: f32,
+    },
+    /// Per-channel median over a `(2 * radius + 1)` square window. Each pixel
+    /// costs O(`radius`) with a sliding histogram, so the whole image is
+    /// O(width × height × `radius`); keep it small on large images.
+    MedianBlur {
+        radius: u32,
+    },
+    /// Draws a `width` pixel ring of `color` behind every pixel at least half
+    /// opaque, like a sticker border. The canvas is not enlarged, so pad the
+    /// image first if the subject touches its edges.
+    StickerOutline {
+        width: u32,
+        color: [u8; 4],
+    },
+    /// Equalizes the histogram of the YCbCr luma, leaving the chroma and so
+    /// the hues intact, or of each color channel when `per_channel` is set.
+    EqualizeHistogram {
+        #[cfg_attr(feature = "serde", serde(default))]
+        per_channel: bool,
+    },
+    /// Gray-world white balance: scales each color channel so its mean
+    /// matches the mean of all three. Channels with a zero mean are kept.
+    AutoWhiteBalance,
+    /// Difference-of-gaussians line art. Luminance (in `0.0..=1.0`) is blurred
+    /// with the smaller and the larger of the two sigmas, and pixels where the
+    /// narrow blur falls more than `threshold` below the wide one turn black
+    /// on white. A sigma ratio around 1.6 approximates a Laplacian of
+    /// Gaussian; larger ratios give bolder, coarser lines.
+    DoG {
+        sigma1: f32,
+        sigma2: f32,
+        threshold: f32,
+    },
+}
+
+impl ImageOperation {
+    fn scaled(self, factor: f32) -> Self {
+        let size = |v: u32| ((v as f32 * factor).round() as u32).max(1);
+        let coord = |v: u32| (v as f32 * factor).round() as u32;
+        let rect = |(x, y, w, h): (u32, u32, u32, u32)| (coord(x), coord(y), size(w), size(h));
+        match self {
+            Self::Thumbnail { w, h, exact } => Self::Thumbnail {
+                w: size(w),
+                h: size(h),
+                exact,
+            },
+            Self::Resize { h, w, filter, mode } => Self::Resize {
+                h: size(h),
+                w: size(w),
+                filter,
+                mode,
+            },
+            Self::Crop { x, y, w, h } => Self::Crop {
+                x: coord(x),
+                y: coord(y),
+                w: size(w),
+                h: size(h),
+            },
+            Self::Overlay {
+                layer_image_input,
+                coords,
+                mode,
+            } => Self::Overlay {
+                layer_image_input,
+                coords: (
+                    (coords.0 as f32 * factor) as i64,
+                    (coords.1 as f32 * factor) as i64,
+                ),
+                mode,
+            },
+            Self::DrawText {
+                text,
+                color,
+                font,
+                scale,
+                mid,
+                max_width,
+            } => Self::DrawText {
+                text,
+                color,
+                font,
+                scale: ScaleTuple(scale.0 * factor, scale.1 * factor),
+                mid: (
+                    (mid.0 as f32 * factor) as i32,
+                    (mid.1 as f32 * factor) as i32,
+                ),
+                max_width,
+            },
+            Self::Blur { sigma } => Self::Blur {
+                sigma: sigma * factor,
+            },
+            Self::Unsharpen { sigma, threshold } => Self::Unsharpen {
+                sigma: sigma * factor,
+                threshold,
+            },
+            Self::Region { rect: r, ops } => Self::Region {
+                rect: rect(r),
+                ops: ops.into_iter().map(|op| op.scaled(factor)).collect(),
+            },
+            Self::NinePatch {
+                patch,
+                insets,
+                target,
+            } => Self::NinePatch {
+                patch,
+                insets,
+                target: (size(target.0), size(target.1)),
+            },
+            Self::PlaceInQuad { content, corners } => Self::PlaceInQuad {
+                content,
+                corners: corners.map(|(x, y)| (x * factor, y * factor)),
+            },
+            Self::StackBlur { radius } => Self::StackBlur {
+                radius: coord(radius),
+            },
+            Self::TiltShift {
+                focus_y,
+                focus_height,
+                max_sigma,
+            } => Self::TiltShift {
+                focus_y: coord(focus_y),
+                focus_height: coord(focus_height),
+                max_sigma: max_sigma * factor,
+            },
+            Self::Halftone {
+                dot_size,
+                angle,
+                color,
+            } => Self::Halftone {
+                dot_size: size(dot_size),
+                angle,
+                color,
+            },
+            Self::OilPaint { radius, levels } => Self::OilPaint {
+                radius: coord(radius),
+                levels,
+            },
+            Self::RemoveRedEye { regions } => Self::RemoveRedEye {
+                regions: regions.into_iter().map(rect).collect(),
+            },
+            Self::OverlayRounded {
+                layer_image_input,
+                coords,
+                radius,
+            } => Self::OverlayRounded {
+                layer_image_input,
+                coords: (
+                    (coords.0 as f32 * factor) as i64,
+                    (coords.1 as f32 * factor) as i64,
+                ),
+                radius: coord(radius),
+            },
+            Self::PaletteStrip {
+                k,
+                position,
+                thickness,
+            } => Self::PaletteStrip {
+                k,
+                position,
+                thickness: size(thickness),
+            },
+            Self::FadeEdges { width } => Self::FadeEdges {
+                width: coord(width),
+            },
+            Self::Scanlines { intensity, spacing } => Self::Scanlines {
+                intensity,
+                spacing: size(spacing),
+            },
+            Self::PencilSketch { strength } => Self::PencilSketch {
+                strength: strength * factor,
+            },
+            Self::DrawTextWave {
+                text,
+                font,
+                scale,
+                color,
+                mid,
+                amplitude,
+                wavelength,
+            } => Self::DrawTextWave {
+                text,
+                font,
+                scale: ScaleTuple(scale.0 * factor, scale.1 * factor),
+                color,
+                mid: (
+                    (mid.0 as f32 * factor) as i32,
+                    (mid.1 as f32 * factor) as i32,
+                ),
+                amplitude: amplitude * factor,
+                wavelength: wavelength * factor,
+            },
+            Self::DisplacementMap { map, scale } => Self::DisplacementMap {
+                map,
+                scale: scale * factor,
+            },
+            Self::Bloom {
+                threshold,
+                sigma,
+                intensity,
+            } => Self::Bloom {
+                threshold,
+                sigma: sigma * factor,
+                intensity,
+            },
+            Self::ProgressBar {
+                rect: r,
+                progress,
+                fill,
+                bg,
+                rounded,
+            } => Self::ProgressBar {
+                rect: rect(r),
+                progress,
+                fill,
+                bg,
+                rounded,
+            },
+            Self::Orton {
+                blur_sigma,
+                brightness,
+                blend,
+            } => Self::Orton {
+                blur_sigma: blur_sigma * factor,
+                brightness,
+                blend,
+            },
+            Self::SeamlessClone {
+                source,
+                mask,
+                coords,
+            } => Self::SeamlessClone {
+                source,
+                mask,
+                coords: (
+                    (coords.0 as f32 * factor) as i64,
+                    (coords.1 as f32 * factor) as i64,
+                ),
+            },
+            Self::FrostedGlass { radius, seed } => Self::FrostedGlass {
+                radius: coord(radius),
+                seed,
+            },
+            Self::DodgeBurn {
+                center,
+                radius,
+                amount,
+            } => Self::DodgeBurn {
+                center: (
+                    (center.0 as f32 * factor) as i32,
+                    (center.1 as f32 * factor) as i32,
+                ),
+                radius: radius * factor,
+                amount,
+            },
+            Self::Kaleidoscope { segments, center } => Self::Kaleidoscope {
+                segments,
+                center: (
+                    (center.0 as f32 * factor) as i32,
+                    (center.1 as f32 * factor) as i32,
+                ),
+            },
+            Self::ThumbnailSmooth { w, h } => Self::ThumbnailSmooth {
+                w: size(w),
+                h: size(h),
+            },
+            Self::DrawRect {
+                x,
+                y,
+                w,
+                h,
+                color,
+                filled,
+            } => Self::DrawRect {
+                x: (x as f32 * factor) as i32,
+                y: (y as f32 * factor) as i32,
+                w: size(w),
+                h: size(h),
+                color,
+                filled,
+            },
+            Self::Grid {
+                spacing,
+                color,
+                labels,
+                font,
+            } => Self::Grid {
+                spacing: size(spacing),
+                color,
+                labels,
+                font,
+            },
+            #[cfg(feature = "exif")]
+            Self::BurnExif {
+                fields,
+                font,
+                scale,
+                color,
+                anchor,
+            } => Self::BurnExif {
+                fields,
+                font,
+                scale: ScaleTuple(scale.0 * factor, scale.1 * factor),
+                color,
+                anchor,
+            },
+            Self::DrawLine {
+                from,
+                to,
+                color,
+                thickness,
+            } => Self::DrawLine {
+                from: (from.0 * factor, from.1 * factor),
+                to: (to.0 * factor, to.1 * factor),
+                color,
+                thickness: size(thickness),
+            },
+            Self::DrawCircle {
+                cx,
+                cy,
+                radius,
+                color,
+                filled,
+            } => Self::DrawCircle {
+                cx: (cx as f32 * factor) as i32,
+                cy: (cy as f32 * factor) as i32,
+                radius: (radius as f32 * factor) as i32,
+                color,
+                filled,
+            },
+            Self::DrawEllipse {
+                cx,
+                cy,
+                rx,
+                ry,
+                color,
+                filled,
+            } => Self::DrawEllipse {
+                cx: (cx as f32 * factor) as i32,
+                cy: (cy as f32 * factor) as i32,
+                rx: (rx as f32 * factor) as i32,
+                ry: (ry as f32 * factor) as i32,
+                color,
+                filled,
+            },
+            Self::Pad {
+                top,
+                right,
+                bottom,
+                left,
+                color,
+            } => Self::Pad {
+                top: coord(top),
+                right: coord(right),
+                bottom: coord(bottom),
+                left: coord(left),
+                color,
+            },
+            Self::RoundCorners { radius } => Self::RoundCorners {
+                radius: coord(radius),
+            },
+            Self::Pixelate { block_size, region } => Self::Pixelate {
+                block_size: size(block_size),
+                region: region.map(rect),
+            },
+            Self::DenoiseNLM {
+                h,
+                template,
+                search,
+            } => Self::DenoiseNLM {
+                h,
+                template: coord(template),
+                search: coord(search),
+            },
+            #[cfg(feature = "chrono")]
+            Self::Timestamp {
+                format,
+                font,
+                scale,
+                color,
+                anchor,
+                time,
+            } => Self::Timestamp {
+                format,
+                font,
+                scale: ScaleTuple(scale.0 * factor, scale.1 * factor),
+                color,
+                anchor,
+                time,
+            },
+            Self::Spherize {
+                center,
+                radius,
+                strength,
+            } => Self::Spherize {
+                center: (
+                    (center.0 as f32 * factor) as i32,
+                    (center.1 as f32 * factor) as i32,
+                ),
+                radius: radius * factor,
+                strength,
+            },
+            Self::MedianBlur { radius } => Self::MedianBlur {
+                radius: coord(radius),
+            },
+            Self::StickerOutline { width, color } => Self::StickerOutline {
+                width: coord(width),
+                color,
+            },
+            Self::DoG {
+                sigma1,
+                sigma2,
+                threshold,
+            } => Self::DoG {
+                sigma1: sigma1 * factor,
+                sigma2: sigma2 * factor,
+                threshold,
+            },
+            operation => operation,
+        }
+    }
+
+    /// Applies the operation with the pipeline input's EXIF block at hand.
+    #[cfg_attr(not(feature = "exif"), allow(unused_variables))]
+    fn apply_with_exif(
+        self,
+        image: DynamicImage,
+        exif: Option<&[u8]>,
+    ) -> Result<DynamicImage, Errors> {
+        match self {
+            #[cfg(feature = "exif")]
+            Self::BurnExif {
+                fields,
+                font,
+                scale,
+                color,
+                anchor,
+            } => {
+                let caption = fields
+                    .iter()
+                    .filter_map(|field| exif::read_exif_field(exif?, field))
+                    .collect::<Vec<_>>()
+                    .join("  ");
+                let mut image = image;
+                draw_text_anchored(
+                    &mut image,
+                    Rgba(color),
+                    &font.get_font()?,
+                    &caption,
+                    scale.to_scale(),
+                    anchor,
+                );
+                Ok(image)
+            }
+            op => op.apply(image),
+        }
+    }
+
+    fn apply(self, mut image: DynamicImage) -> Result<DynamicImage, Errors> {
+        match self {
+            Self::Thumbnail { h, w, exact } => Ok(if exact {
+                image.thumbnail_exact(w, h)
+            } else {
+                image.thumbnail(w, h)
+            }),
+            Self::Resize { h, w, filter, mode } => Ok(mode.resize(&image, w, h, filter.into())),
+            Self::Crop { x, y, w, h } => Ok(image.crop_imm(x, y, w, h)),
+            Self::Overlay {
+                layer_image_input,
+                coords,
+                mode,
+            } => {
+                let layer = layer_image_input.get_image()?;
+                match mode {
+                    BlendMode::Normal => imageops::overlay(&mut image, &layer, coords.0, coords.1),
+                    mode => blend_layer(&mut image, &layer, coords, mode),
+                }
+                Ok(image)
+            }
+            Self::Tile { tile_image } => {
+                image::imageops::tile(&mut image, &tile_image.get_image()?);
+                Ok(image)
+            }
+            Self::DrawText {
+                mut text,
+                color,
+                font,
+                scale,
+                mid,
+                max_width,
+            } => {
+                if let Some(width) = max_width {
+                    text = textwrap::fill(&text, width);
+                }
+                let color = Rgba(color);
+                draw_text(
+                    &mut image,
+                    color,
+                    &font.get_font()?,
+                    &text,
+                    scale.to_scale(),
+                    &mid,
+                );
+                Ok(image)
+            }
+            Self::ColorBlend { r, g, b } => {
+                let color = [r, g, b];
+                let width = image.width();
+                match &mut image {
+                    DynamicImage::ImageRgb8(buffer) => color_blend(buffer, 3, width, color),
+                    DynamicImage::ImageRgba8(buffer) => color_blend(buffer, 4, width, color),
+                    _ => {
+                        let h = image.height();
+                        let w = image.width();
+
+                        (0..w).for_each(|x| {
+                            (0..h).for_each(|y| {
+                                let mut pixel = image.get_pixel(x, y);
+                                (0..3).for_each(|i| {
+                                    pixel[i] = pixel[i] / 2 + color[i] / 2;
+                                });
+                                image.put_pixel(x, y, pixel);
+                            })
+                        });
+                    }
+                }
+                Ok(image)
+            }
+            Self::Blur { sigma } => Ok(image.blur(sigma)),
+            Self::Unsharpen { sigma, threshold } => {
+                Ok(image::imageops::unsharpen(&image, sigma, threshold).into())
+            }
+            Self::Brighten(value) => Ok(image.brighten(value)),
+            Self::AdjustContrast(value) => Ok(image.adjust_contrast(value)),
+            Self::Gamma(gamma) => {
+                if gamma <= 0.0 || !gamma.is_finite() {
+                    return Err(Errors::InvalidGamma);
+                }
+                let table: Vec<u8> = (0..=255)
+                    .map(|v| (255.0 * (v as f32 / 255.0).powf(1.0 / gamma)).round() as u8)
+                    .collect();
+                let (w, h) = image.dimensions();
+                for x in 0..w {
+                    for y in 0..h {
+                        let mut pixel = image.get_pixel(x, y);
+                        (0..3).for_each(|i| pixel[i] = table[pixel[i] as usize]);
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+                Ok(image)
+            }
+            Self::HueRotate(value) => Ok(image.huerotate(value)),
+            Self::Invert => {
+                image.invert();
+                Ok(image)
+            }
+            Self::Grayscale => Ok(image::imageops::grayscale(&image).into()),
+            Self::FlipHorizontal => Ok(image.fliph()),
+            Self::FlipVertical => Ok(image.flipv()),
+            Self::Rotate90 => Ok(image.rotate90()),
+            Self::Rotate180 => Ok(image.rotate180()),
+            Self::Rotate270 => Ok(image.rotate270()),
+            Self::Region { rect, ops } => {
+                let (x, y, w, h) = rect;
+                let mut region = image.crop_imm(x, y, w, h);
+                let size = region.dimensions();
+                for op in ops.into_iter() {
+                    region = op.apply(region)?;
+                }
+                if region.dimensions() != size {
+                    return Err(Errors::RegionSizeChanged);
+                }
+                imageops::replace(&mut image, &region, x.into(), y.into());
+                Ok(image)
+            }
+            Self::NinePatch {
+                patch,
+                insets,
+                target,
+            } => nine_patch(&patch.get_image()?, insets, target),
+            Self::PlaceInQuad { content, corners } => {
+                let content = content.get_image()?.into_rgba8();
+                let (w, h) = content.dimensions();
+                let from = [
+                    (0.0, 0.0),
+                    (w as f32, 0.0),
+                    (w as f32, h as f32),
+                    (0.0, h as f32),
+                ];
+                let projection =
+                    Projection::from_control_points(from, corners).ok_or(Errors::InvalidQuad)?;
+                let mut layer = RgbaImage::new(image.width(), image.height());
+                warp_into(
+                    &content,
+                    &projection,
+                    Interpolation::Bilinear,
+                    Rgba([0, 0, 0, 0]),
+                    &mut layer,
+                );
+                imageops::overlay(&mut image, &DynamicImage::ImageRgba8(layer), 0, 0);
+                Ok(image)
+            }
+            Self::AverageColorFill => {
+                let color = average_color(&image);
+                let size = image.dimensions();
+                Ok(if image.color().has_alpha() {
+                    DynamicImage::ImageRgba8(RgbaImage::from_pixel(size.0, size.1, color))
+                } else {
+                    DynamicImage::ImageRgb8(fill_color(color.to_rgb().0, size))
+                })
+            }
+            Self::StackBlur { radius } => {
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                stack_blur(&mut buffer, radius);
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::TiltShift {
+                focus_y,
+                focus_height,
+                max_sigma,
+            } => {
+                let alpha = image.color().has_alpha();
+                Ok(with_alpha(
+                    tilt_shift(&image.into_rgba8(), focus_y, focus_height, max_sigma),
+                    alpha,
+                ))
+            }
+            Self::RemoveRedEye { regions } => {
+                for (rx, ry, rw, rh) in regions {
+                    let x_end = rx.saturating_add(rw).min(image.width());
+                    let y_end = ry.saturating_add(rh).min(image.height());
+                    for x in rx..x_end {
+                        for y in ry..y_end {
+                            let mut pixel = image.get_pixel(x, y);
+                            let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+                            if r > RED_EYE_MIN_RED && r > RED_EYE_RATIO * (g + b) / 2.0 {
+                                pixel[0] = ((g + b) / 2.0) as u8;
+                                image.put_pixel(x, y, pixel);
+                            }
+                        }
+                    }
+                }
+                Ok(image)
+            }
+            Self::FramedBy { frame, fit } => {
+                let frame = frame.get_image()?;
+                let (w, h) = frame.dimensions();
+                let photo = fit.resize(&image, w, h, FilterType::Lanczos3);
+                let mut canvas = DynamicImage::new_rgba8(w, h);
+                imageops::overlay(
+                    &mut canvas,
+                    &photo,
+                    (w as i64 - photo.width() as i64) / 2,
+                    (h as i64 - photo.height() as i64) / 2,
+                );
+                imageops::overlay(&mut canvas, &frame, 0, 0);
+                Ok(canvas)
+            }
+            Self::Clahe { tiles, clip_limit } => {
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                clahe(&mut buffer, tiles, clip_limit);
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::OverlayRounded {
+                layer_image_input,
+                coords,
+                radius,
+            } => {
+                let mut layer = layer_image_input.get_image()?.into_rgba8();
+                round_corners(&mut layer, radius);
+                imageops::overlay(&mut image, &layer, coords.0, coords.1);
+                Ok(image)
+            }
+            Self::SelectiveColor { hue, tolerance } => {
+                let (w, h) = image.dimensions();
+                for x in 0..w {
+                    for y in 0..h {
+                        let mut pixel = image.get_pixel(x, y);
+                        let (pixel_hue, _, _) = rgb_to_hsl(pixel.to_rgb());
+                        let distance = (pixel_hue - hue).rem_euclid(360.0);
+                        if distance.min(360.0 - distance) > tolerance {
+                            let value = luminance(pixel.to_rgb());
+                            (0..3).for_each(|i| pixel[i] = value);
+                            image.put_pixel(x, y, pixel);
+                        }
+                    }
+                }
+                Ok(image)
+            }
+            Self::OilPaint { radius, levels } => {
+                let alpha = image.color().has_alpha();
+                Ok(with_alpha(
+                    oil_paint(&image.into_rgba8(), radius, levels),
+                    alpha,
+                ))
+            }
+            Self::Halftone {
+                dot_size,
+                angle,
+                color,
+            } => Ok(DynamicImage::ImageRgba8(halftone(
+                &image,
+                dot_size,
+                angle,
+                Rgba(color),
+            ))),
+            Self::OrderedDither { levels, matrix } => {
+                if levels < 2 || ![2, 4, 8].contains(&matrix) {
+                    return Err(Errors::InvalidDitherParameters);
+                }
+                let bayer = bayer_matrix(matrix as usize);
+                let n = matrix as u32;
+                let step = 255.0 / (levels - 1) as f32;
+                let (w, h) = image.dimensions();
+                for x in 0..w {
+                    for y in 0..h {
+                        let threshold = (bayer[(y % n) as usize][(x % n) as usize] as f32 + 0.5)
+                            / (n * n) as f32
+                            - 0.5;
+                        let mut pixel = image.get_pixel(x, y);
+                        (0..3).for_each(|i| {
+                            let level = (pixel[i] as f32 / step + threshold)
+                                .round()
+                                .clamp(0.0, (levels - 1) as f32);
+                            pixel[i] = (level * step).round() as u8;
+                        });
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+                Ok(image)
+            }
+            Self::PaletteStrip {
+                k,
+                position,
+                thickness,
+            } => {
+                let colors = dominant_colors(&image, k);
+                if colors.is_empty() {
+                    return Ok(image);
+                }
+                let (w, h) = image.dimensions();
+                let vertical = matches!(position, Anchor::Left | Anchor::Right);
+                let (strip_w, strip_h) = if vertical {
+                    (thickness.min(w), h)
+                } else {
+                    (w, thickness.min(h))
+                };
+                let length = if vertical { strip_h } else { strip_w };
+                let strip = RgbaImage::from_fn(strip_w, strip_h, |x, y| {
+                    let along = if vertical { y } else { x };
+                    let index =
+                        (along as usize * colors.len() / length as usize).min(colors.len() - 1);
+                    colors[index].to_rgba()
+                });
+                let (x, y) = position.position((w, h), (strip_w, strip_h));
+                imageops::overlay(&mut image, &strip, x, y);
+                Ok(image)
+            }
+            Self::FitAspect {
+                ratio_w,
+                ratio_h,
+                pad_color,
+                prefer,
+            } => {
+                if ratio_w == 0 || ratio_h == 0 || image.width() == 0 || image.height() == 0 {
+                    return Err(Errors::InvalidDimensions);
+                }
+                let (w, h) = (image.width() as u64, image.height() as u64);
+                let (rw, rh) = (ratio_w as u64, ratio_h as u64);
+                let (crop, pad) = if w * rh > h * rw {
+                    (((h * rw / rh).max(1), h), (w, (w * rh).div_ceil(rw)))
+                } else {
+                    ((w, (w * rh / rw).max(1)), ((h * rw).div_ceil(rh), h))
+                };
+                let use_crop = match prefer {
+                    Prefer::Crop => true,
+                    Prefer::Pad => false,
+                    Prefer::Auto => w * h - crop.0 * crop.1 <= pad.0 * pad.1 - w * h,
+                };
+                if use_crop {
+                    let (cw, ch) = (crop.0 as u32, crop.1 as u32);
+                    Ok(image.crop_imm((image.width() - cw) / 2, (image.height() - ch) / 2, cw, ch))
+                } else {
+                    let (pw, ph) = (pad.0 as u32, pad.1 as u32);
+                    let mut canvas =
+                        DynamicImage::ImageRgba8(RgbaImage::from_pixel(pw, ph, Rgba(pad_color)));
+                    imageops::overlay(
+                        &mut canvas,
+                        &image,
+                        ((pw - image.width()) / 2).into(),
+                        ((ph - image.height()) / 2).into(),
+                    );
+                    Ok(canvas)
+                }
+            }
+            Self::InvertAlpha => {
+                if !image.color().has_alpha() {
+                    return Ok(image);
+                }
+                let mut buffer = image.into_rgba8();
+                buffer.pixels_mut().for_each(|p| p[3] = 255 - p[3]);
+                Ok(DynamicImage::ImageRgba8(buffer))
+            }
+            Self::FadeEdges { width } => {
+                let mut buffer = image.into_rgba8();
+                let (w, h) = buffer.dimensions();
+                let width = width.min(w / 2).min(h / 2);
+                if width == 0 {
+                    return Ok(DynamicImage::ImageRgba8(buffer));
+                }
+                for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                    let edge = x.min(y).min(w - 1 - x).min(h - 1 - y);
+                    if edge < width {
+                        let factor = edge as f32 / width as f32;
+                        pixel[3] = (pixel[3] as f32 * factor).round() as u8;
+                    }
+                }
+                Ok(DynamicImage::ImageRgba8(buffer))
+            }
+            Self::Scanlines { intensity, spacing } => {
+                let factor = 1.0 - intensity.clamp(0.0, 1.0);
+                let (w, h) = image.dimensions();
+                for y in (0..h).step_by(spacing.max(1) as usize) {
+                    for x in 0..w {
+                        let mut pixel = image.get_pixel(x, y);
+                        (0..3).for_each(|i| pixel[i] = (pixel[i] as f32 * factor) as u8);
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+                Ok(image)
+            }
+            Self::PencilSketch { strength } => {
+                let gray = image.to_luma8();
+                let mut inverted = gray.clone();
+                imageops::invert(&mut inverted);
+                let blurred = imageops::blur(&inverted, strength);
+                Ok(DynamicImage::ImageLuma8(GrayImage::from_fn(
+                    gray.width(),
+                    gray.height(),
+                    |x, y| {
+                        let base = gray.get_pixel(x, y)[0] as u32;
+                        let blend = blurred.get_pixel(x, y)[0] as u32;
+                        Luma([color_dodge(base, blend)])
+                    },
+                )))
+            }
+            Self::DrawTextWave {
+                text,
+                font,
+                scale,
+                color,
+                mid,
+                amplitude,
+                wavelength,
+            } => {
+                let font = font.get_font()?;
+                let scale = scale.to_scale();
+                let text_width = measure_line_width(&font, &text, scale);
+                let left = mid.0 as f32 - text_width / 2.0;
+                let top = mid.1 as f32 - get_font_height(&font, scale) / 2.0;
+                let wavelength = if wavelength == 0.0 { 1.0 } else { wavelength };
+                for (character, glyph) in
+                    text.chars().zip(font.layout(&text, scale, point(0.0, 0.0)))
+                {
+                    let x = glyph.position().x;
+                    let offset = amplitude * (x / wavelength).sin();
+                    draw_text_mut(
+                        &mut image,
+                        Rgba(color),
+                        (left + x) as i32,
+                        (top + offset) as i32,
+                        scale,
+                        &font,
+                        &character.to_string(),
+                    );
+                }
+                Ok(image)
+            }
+            Self::DisplacementMap { map, scale } => {
+                let (w, h) = image.dimensions();
+                if w == 0 || h == 0 {
+                    return Ok(image);
+                }
+                let map = map
+                    .get_image()?
+                    .resize_exact(w, h, FilterType::Triangle)
+                    .into_rgb8();
+                let mut output = image.clone();
+                for (x, y, offset) in map.enumerate_pixels() {
+                    let dx = (offset[0] as f32 - 128.0) * scale;
+                    let dy = (offset[1] as f32 - 128.0) * scale;
+                    let sx = (x as f32 + dx).round().clamp(0.0, (w - 1) as f32) as u32;
+                    let sy = (y as f32 + dy).round().clamp(0.0, (h - 1) as f32) as u32;
+                    output.put_pixel(x, y, image.get_pixel(sx, sy));
+                }
+                Ok(output)
+            }
+            Self::Bloom {
+                threshold,
+                sigma,
+                intensity,
+            } => {
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                let highlights = RgbImage::from_fn(buffer.width(), buffer.height(), |x, y| {
+                    let pixel = buffer.get_pixel(x, y).to_rgb();
+                    if luminance(pixel) > threshold {
+                        pixel
+                    } else {
+                        Rgb([0, 0, 0])
+                    }
+                });
+                let glow = imageops::blur(&highlights, sigma);
+                for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                    let light = glow.get_pixel(x, y);
+                    (0..3).for_each(|i| {
+                        pixel[i] =
+                            (pixel[i] as f32 + light[i] as f32 * intensity).clamp(0.0, 255.0) as u8
+                    });
+                }
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::ProgressBar {
+                rect,
+                progress,
+                fill,
+                bg,
+                rounded,
+            } => {
+                let (x, y, w, h) = rect;
+                let filled = (w as f32 * progress.clamp(0.0, 1.0)).round() as u32;
+                let mut background = RgbaImage::from_pixel(w, h, Rgba(bg));
+                let mut bar = RgbaImage::from_pixel(filled, h, Rgba(fill));
+                if rounded {
+                    round_corners(&mut background, h / 2);
+                    round_corners(&mut bar, h / 2);
+                }
+                imageops::overlay(&mut image, &background, x.into(), y.into());
+                imageops::overlay(&mut image, &bar, x.into(), y.into());
+                Ok(image)
+            }
+            Self::Orton {
+                blur_sigma,
+                brightness,
+                blend,
+            } => {
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                let mut bright = buffer.clone();
+                bright.pixels_mut().for_each(|p| {
+                    (0..3).for_each(|i| p[i] = (p[i] as f32 * brightness).clamp(0.0, 255.0) as u8)
+                });
+                let glow = imageops::blur(&bright, blur_sigma);
+                let blend = blend.clamp(0.0, 1.0);
+                for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                    let light = glow.get_pixel(x, y);
+                    (0..3).for_each(|i| {
+                        let base = pixel[i] as f32 / 255.0;
+                        let screen = 1.0 - (1.0 - base) * (1.0 - light[i] as f32 / 255.0);
+                        pixel[i] = ((base * (1.0 - blend) + screen * blend) * 255.0).round() as u8;
+                    });
+                }
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::NamedPalette { palette, dither } => {
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                quantize_to_palette(&mut buffer, &palette.colors(), dither);
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::SeamlessClone {
+                source,
+                mask,
+                coords,
+            } => {
+                let alpha = image.color().has_alpha();
+                let source = source.get_image()?.into_rgb8();
+                let (sw, sh) = source.dimensions();
+                let mask = mask
+                    .get_image()?
+                    .resize_exact(sw, sh, FilterType::Triangle)
+                    .into_luma8();
+                let mut buffer = image.into_rgba8();
+                seamless_clone(&mut buffer, &source, &mask, coords);
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::FrostedGlass { radius, seed } => {
+                let mut rng = seed.map_or_else(random::Rng::from_global, random::Rng::new);
+                let (w, h) = image.dimensions();
+                let radius = radius.min(w.max(h));
+                let span = 2 * radius + 1;
+                let mut output = image.clone();
+                for y in 0..h {
+                    for x in 0..w {
+                        let sx = (x + rng.below(span)).saturating_sub(radius).min(w - 1);
+                        let sy = (y + rng.below(span)).saturating_sub(radius).min(h - 1);
+                        output.put_pixel(x, y, image.get_pixel(sx, sy));
+                    }
+                }
+                Ok(output)
+            }
+            Self::AutoEnhance => {
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                auto_contrast(&mut buffer, AUTO_ENHANCE_CLIP);
+                adjust_saturation(&mut buffer, AUTO_ENHANCE_SATURATION);
+                let buffer = imageops::unsharpen(
+                    &buffer,
+                    AUTO_ENHANCE_SHARPEN_SIGMA,
+                    AUTO_ENHANCE_SHARPEN_THRESHOLD,
+                );
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::DodgeBurn {
+                center,
+                radius,
+                amount,
+            } => {
+                let amount = amount.clamp(-1.0, 1.0);
+                let (w, h) = image.dimensions();
+                for x in 0..w {
+                    for y in 0..h {
+                        let dx = x as f32 - center.0 as f32;
+                        let dy = y as f32 - center.1 as f32;
+                        let distance = dx.hypot(dy) / radius;
+                        if distance >= 1.0 || distance.is_nan() {
+                            continue;
+                        }
+                        let weight = (1.0 - distance * distance).powi(2) * amount;
+                        let mut pixel = image.get_pixel(x, y);
+                        (0..3).for_each(|i| {
+                            let v = pixel[i] as f32;
+                            let adjusted = if weight > 0.0 {
+                                v + (255.0 - v) * weight
+                            } else {
+                                v * (1.0 + weight)
+                            };
+                            pixel[i] = adjusted.round().clamp(0.0, 255.0) as u8;
+                        });
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+                Ok(image)
+            }
+            Self::Particles {
+                kind,
+                count,
+                seed,
+                frame,
+            } => {
+                draw_particles(&mut image, kind, count, seed, frame);
+                Ok(image)
+            }
+            Self::Sepia => {
+                let (w, h) = image.dimensions();
+                for x in 0..w {
+                    for y in 0..h {
+                        let mut pixel = image.get_pixel(x, y);
+                        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|v| v as f32);
+                        pixel[0] = (0.393 * r + 0.769 * g + 0.189 * b).round().min(255.0) as u8;
+                        pixel[1] = (0.349 * r + 0.686 * g + 0.168 * b).round().min(255.0) as u8;
+                        pixel[2] = (0.272 * r + 0.534 * g + 0.131 * b).round().min(255.0) as u8;
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+                Ok(image)
+            }
+            Self::Kaleidoscope { segments, center } => {
+                if segments < 2 {
+                    return Err(Errors::InvalidSegments);
+                }
+                let source = image.into_rgba8();
+                let (w, h) = source.dimensions();
+                let wedge = std::f32::consts::TAU / segments as f32;
+                let (cx, cy) = (center.0 as f32, center.1 as f32);
+                Ok(DynamicImage::ImageRgba8(RgbaImage::from_fn(
+                    w,
+                    h,
+                    |x, y| {
+                        let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+                        let angle = dy.atan2(dx).rem_euclid(std::f32::consts::TAU);
+                        let mut local = angle % wedge;
+                        if (angle / wedge) as u32 % 2 == 1 {
+                            local = wedge - local;
+                        }
+                        let radius = dx.hypot(dy);
+                        let sx = (cx + radius * local.cos()).round();
+                        let sy = (cy + radius * local.sin()).round();
+                        if sx < 0.0 || sy < 0.0 || sx >= w as f32 || sy >= h as f32 {
+                            Rgba([0, 0, 0, 0])
+                        } else {
+                            *source.get_pixel(sx as u32, sy as u32)
+                        }
+                    },
+                )))
+            }
+            Self::RotateAngle {
+                degrees,
+                background,
+            } => {
+                let alpha = image.color().has_alpha();
+                Ok(with_alpha(
+                    rotate_expand(&image.into_rgba8(), degrees, Rgba(background)),
+                    alpha,
+                ))
+            }
+            Self::RotateCrop { degrees } => {
+                let alpha = image.color().has_alpha();
+                if quarter_turns(degrees).is_some() {
+                    return Ok(with_alpha(
+                        rotate_expand(&image.into_rgba8(), degrees, Rgba([0, 0, 0, 0])),
+                        alpha,
+                    ));
+                }
+                // Inset by a pixel per side so bilinear fringes from the
+                // transparent fill never reach the crop.
+                let (crop_w, crop_h) = inscribed_rect_size(image.dimensions(), degrees);
+                let (crop_w, crop_h) = (
+                    crop_w.saturating_sub(2).max(1),
+                    crop_h.saturating_sub(2).max(1),
+                );
+                let rotated = rotate_expand(&image.into_rgba8(), degrees, Rgba([0, 0, 0, 0]));
+                let (crop_w, crop_h) = (crop_w.min(rotated.width()), crop_h.min(rotated.height()));
+                let (x, y) = Anchor::Center.position(rotated.dimensions(), (crop_w, crop_h));
+                let cropped =
+                    imageops::crop_imm(&rotated, x as u32, y as u32, crop_w, crop_h).to_image();
+                Ok(with_alpha(cropped, alpha))
+            }
+            Self::EdgeSharpen {
+                amount,
+                edge_threshold,
+            } => {
+                let alpha = image.color().has_alpha();
+                let edges = sobel_gradients(&image.to_luma8());
+                let mut buffer = image.into_rgba8();
+                let blurred = imageops::blur(&buffer, EDGE_SHARPEN_SIGMA);
+                for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                    let strength = edges.get_pixel(x, y)[0] as f32 / SOBEL_MAX_MAGNITUDE;
+                    if strength <= edge_threshold {
+                        continue;
+                    }
+                    let soft = blurred.get_pixel(x, y);
+                    (0..3).for_each(|i| {
+                        let v = pixel[i] as f32;
+                        pixel[i] = (v + amount * (v - soft[i] as f32)).clamp(0.0, 255.0) as u8;
+                    });
+                }
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::AdjustSaturation(factor) => {
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                adjust_saturation(&mut buffer, factor);
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::ThumbnailSmooth { w, h } => {
+                let factor = (image.width() as f32 / w.max(1) as f32)
+                    .max(image.height() as f32 / h.max(1) as f32);
+                if factor > 1.0 {
+                    image = image.blur((factor - 1.0) / 2.0);
+                }
+                Ok(image.resize(w, h, FilterType::Lanczos3))
+            }
+            Self::Threshold { value, invert } => {
+                let mut gray = image.into_luma8();
+                gray.pixels_mut().for_each(|p| {
+                    let white = value == 0 || p[0] > value;
+                    p[0] = if white != invert { 255 } else { 0 };
+                });
+                Ok(DynamicImage::ImageLuma8(gray))
+            }
+            Self::Grid {
+                spacing,
+                color,
+                labels,
+                font,
+            } => {
+                let spacing = spacing.max(1);
+                let color = Rgba(color);
+                let (w, h) = image.dimensions();
+                for y in 0..h {
+                    for x in 0..w {
+                        if x % spacing == 0 || y % spacing == 0 {
+                            let mut pixel = image.get_pixel(x, y);
+                            pixel.blend(&color);
+                            image.put_pixel(x, y, pixel);
+                        }
+                    }
+                }
+                if labels {
+                    let font = font.ok_or(Errors::InvalidFont)?.get_font()?;
+                    let scale = Scale::uniform(GRID_LABEL_SCALE);
+                    for x in (spacing..w).step_by(spacing as usize) {
+                        draw_text_mut(
+                            &mut image,
+                            color,
+                            x as i32 + 2,
+                            2,
+                            scale,
+                            &font,
+                            &x.to_string(),
+                        );
+                    }
+                    for y in (spacing..h).step_by(spacing as usize) {
+                        draw_text_mut(
+                            &mut image,
+                            color,
+                            2,
+                            y as i32 + 2,
+                            scale,
+                            &font,
+                            &y.to_string(),
+                        );
+                    }
+                }
+                Ok(image)
+            }
+            Self::GraduatedFilter {
+                direction,
+                from_ev,
+                to_ev,
+            } => {
+                let (w, h) = image.dimensions();
+                let progress = |along: u32, length: u32| along as f32 / (length.max(2) - 1) as f32;
+                for x in 0..w {
+                    for y in 0..h {
+                        let t = match direction {
+                            Direction::TopToBottom => progress(y, h),
+                            Direction::BottomToTop => 1.0 - progress(y, h),
+                            Direction::LeftToRight => progress(x, w),
+                            Direction::RightToLeft => 1.0 - progress(x, w),
+                        };
+                        let gain = 2f32.powf(from_ev + (to_ev - from_ev) * t);
+                        let mut pixel = image.get_pixel(x, y);
+                        (0..3).for_each(|i| {
+                            pixel[i] = (pixel[i] as f32 * gain).round().clamp(0.0, 255.0) as u8
+                        });
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+                Ok(image)
+            }
+            Self::Opacity(factor) => {
+                let factor = factor.clamp(0.0, 1.0);
+                let mut buffer = image.into_rgba8();
+                buffer
+                    .pixels_mut()
+                    .for_each(|p| p[3] = (p[3] as f32 * factor).round() as u8);
+                Ok(DynamicImage::ImageRgba8(buffer))
+            }
+            #[cfg(feature = "exif")]
+            op @ Self::BurnExif { .. } => op.apply_with_exif(image, None),
+            Self::ApplyAlphaMask { mask } => {
+                let mut buffer = image.into_rgba8();
+                let (w, h) = buffer.dimensions();
+                let mask = mask
+                    .get_image()?
+                    .resize_exact(w, h, FilterType::Triangle)
+                    .into_luma8();
+                for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                    pixel[3] = mask.get_pixel(x, y)[0];
+                }
+                Ok(DynamicImage::ImageRgba8(buffer))
+            }
+            Self::DrawRect {
+                x,
+                y,
+                w,
+                h,
+                color,
+                filled,
+            } => {
+                if w == 0 || h == 0 {
+                    return Ok(image);
+                }
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                let mut mask = GrayImage::new(buffer.width(), buffer.height());
+                let rect = Rect::at(x, y).of_size(w, h);
+                if filled {
+                    draw_filled_rect_mut(&mut mask, rect, Luma([255]));
+                } else {
+                    draw_hollow_rect_mut(&mut mask, rect, Luma([255]));
+                }
+                blend_mask(&mut buffer, &mask, Rgba(color));
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::DrawLine {
+                from,
+                to,
+                color,
+                thickness,
+            } => {
+                if thickness == 0 {
+                    return Ok(image);
+                }
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                draw_thick_line(&mut buffer, from, to, Rgba(color), thickness);
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::TileRandom { tile_image, seed } => {
+                let tile = tile_image.get_image()?;
+                let (tw, th) = tile.dimensions();
+                if tw == 0 || th == 0 {
+                    return Ok(image);
+                }
+                let mut variants = vec![tile.clone(), tile.fliph(), tile.flipv(), tile.rotate180()];
+                if tw == th {
+                    let rotated = tile.rotate90();
+                    variants.extend([rotated.fliph(), rotated.flipv(), tile.rotate270(), rotated]);
+                }
+                let mut rng = random::Rng::new(seed);
+                for y in (0..image.height()).step_by(th as usize) {
+                    for x in (0..image.width()).step_by(tw as usize) {
+                        let variant = &variants[rng.below(variants.len() as u32) as usize];
+                        imageops::replace(&mut image, variant, x as i64, y as i64);
+                    }
+                }
+                Ok(image)
+            }
+            Self::DrawCircle {
+                cx,
+                cy,
+                radius,
+                color,
+                filled,
+            } => Self::DrawEllipse {
+                cx,
+                cy,
+                rx: radius,
+                ry: radius,
+                color,
+                filled,
+            }
+            .apply(image),
+            Self::DrawEllipse {
+                cx,
+                cy,
+                rx,
+                ry,
+                color,
+                filled,
+            } => {
+                if rx <= 0 || ry <= 0 {
+                    return Ok(image);
+                }
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                let mut mask = GrayImage::new(buffer.width(), buffer.height());
+                if filled {
+                    draw_filled_ellipse_mut(&mut mask, (cx, cy), rx, ry, Luma([255]));
+                } else {
+                    draw_hollow_ellipse_mut(&mut mask, (cx, cy), rx, ry, Luma([255]));
+                }
+                blend_mask(&mut buffer, &mask, Rgba(color));
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::NormalMap { strength } => {
+                let alpha = image.color().has_alpha();
+                let height = image.to_luma8();
+                let (dx, dy) = (horizontal_sobel(&height), vertical_sobel(&height));
+                let mut buffer = image.into_rgba8();
+                let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+                for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                    let gx = dx.get_pixel(x, y)[0] as f32 / SOBEL_MAX_RESPONSE * strength;
+                    // Image rows grow downwards, so the green axis flips sign.
+                    let gy = dy.get_pixel(x, y)[0] as f32 / SOBEL_MAX_RESPONSE * strength;
+                    let length = (gx * gx + gy * gy + 1.0).sqrt();
+                    pixel[0] = encode(-gx / length);
+                    pixel[1] = encode(gy / length);
+                    pixel[2] = encode(1.0 / length);
+                }
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::Trim {
+                tolerance,
+                background,
+            } => {
+                let buffer = image.to_rgba8();
+                let (w, h) = buffer.dimensions();
+                if w == 0 || h == 0 {
+                    return Ok(image);
+                }
+                let background = background.unwrap_or(buffer.get_pixel(0, 0).0);
+                let is_background = |x: u32, y: u32| {
+                    let pixel = buffer.get_pixel(x, y);
+                    (0..4).all(|i| pixel[i].abs_diff(background[i]) <= tolerance)
+                };
+                let row_clear = |y: u32| (0..w).all(|x| is_background(x, y));
+                let column_clear =
+                    |x: u32, rows: &std::ops::Range<u32>| rows.clone().all(|y| is_background(x, y));
+                let Some(top) = (0..h).find(|&y| !row_clear(y)) else {
+                    return Ok(image.crop_imm(0, 0, 1, 1));
+                };
+                let bottom = (top..h).rev().find(|&y| !row_clear(y)).unwrap_or(top);
+                let rows = top..bottom + 1;
+                let left = (0..w).find(|&x| !column_clear(x, &rows)).unwrap_or(0);
+                let right = (left..w)
+                    .rev()
+                    .find(|&x| !column_clear(x, &rows))
+                    .unwrap_or(left);
+                Ok(image.crop_imm(left, top, right - left + 1, bottom - top + 1))
+            }
+            Self::Pad {
+                top,
+                right,
+                bottom,
+                left,
+                color,
+            } => {
+                let (w, h) = image.dimensions();
+                let width = left
+                    .checked_add(w)
+                    .and_then(|v| v.checked_add(right))
+                    .ok_or(Errors::InvalidDimensions)?;
+                let height = top
+                    .checked_add(h)
+                    .and_then(|v| v.checked_add(bottom))
+                    .ok_or(Errors::InvalidDimensions)?;
+                let size = (width, height);
+                let offset = (left, top);
+                let gray = color[0] == color[1] && color[1] == color[2];
+                let opaque = color[3] == 255;
+                let alpha = image.color().has_alpha() || !opaque;
+                let wide = color.map(|c| c as u16 * 257);
+                Ok(match image.color() {
+                    ColorType::L8 if gray && opaque => DynamicImage::ImageLuma8(pad_buffer(
+                        &image.into_luma8(),
+                        size,
+                        offset,
+                        Luma([color[0]]),
+                    )),
+                    ColorType::L8 | ColorType::La8 if gray => {
+                        DynamicImage::ImageLumaA8(pad_buffer(
+                            &image.into_luma_alpha8(),
+                            size,
+                            offset,
+                            LumaA([color[0], color[3]]),
+                        ))
+                    }
+                    ColorType::L16 if gray && opaque => DynamicImage::ImageLuma16(pad_buffer(
+                        &image.into_luma16(),
+                        size,
+                        offset,
+                        Luma([wide[0]]),
+                    )),
+                    ColorType::L16 | ColorType::La16 if gray => {
+                        DynamicImage::ImageLumaA16(pad_buffer(
+                            &image.into_luma_alpha16(),
+                            size,
+                            offset,
+                            LumaA([wide[0], wide[3]]),
+                        ))
+                    }
+                    ColorType::L16 | ColorType::Rgb16 if !alpha => {
+                        DynamicImage::ImageRgb16(pad_buffer(
+                            &image.into_rgb16(),
+                            size,
+                            offset,
+                            Rgb([wide[0], wide[1], wide[2]]),
+                        ))
+                    }
+                    ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+                        DynamicImage::ImageRgba16(pad_buffer(
+                            &image.into_rgba16(),
+                            size,
+                            offset,
+                            Rgba(wide),
+                        ))
+                    }
+                    _ => with_alpha(
+                        pad_buffer(&image.into_rgba8(), size, offset, Rgba(color)),
+                        alpha,
+                    ),
+                })
+            }
+            Self::Cartoon {
+                levels,
+                edge_threshold,
+            } => {
+                let alpha = image.color().has_alpha();
+                let edges = sobel_gradients(&image.to_luma8());
+                let mut buffer = image.into_rgba8();
+                for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                    let strength = edges.get_pixel(x, y)[0] as f32 / SOBEL_MAX_MAGNITUDE;
+                    for channel in pixel.0.iter_mut().take(3) {
+                        *channel = if strength > edge_threshold {
+                            0
+                        } else {
+                            posterize(*channel, levels)
+                        };
+                    }
+                }
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::CylindricalProject { fov } => {
+                let buffer = image.into_rgba8();
+                let (w, h) = buffer.dimensions();
+                if w == 0 || h == 0 {
+                    return Ok(DynamicImage::ImageRgba8(buffer));
+                }
+                let half_fov = fov.clamp(f32::EPSILON, 179.0).to_radians() / 2.0;
+                let focal = w as f32 / 2.0 / half_fov.tan();
+                let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+                let projected = warp_with(
+                    &buffer,
+                    |x, y| {
+                        let theta = (x - cx) / focal;
+                        if theta.abs() >= std::f32::consts::FRAC_PI_2 {
+                            return (-1.0, -1.0);
+                        }
+                        (focal * theta.tan() + cx, (y - cy) / theta.cos() + cy)
+                    },
+                    Interpolation::Bilinear,
+                    Rgba([0, 0, 0, 0]),
+                );
+                Ok(DynamicImage::ImageRgba8(projected))
+            }
+            Self::RoundCorners { radius } => {
+                let mut buffer = image.into_rgba8();
+                round_corners(&mut buffer, radius);
+                Ok(DynamicImage::ImageRgba8(buffer))
+            }
+            Self::CircleCrop => {
+                let mut buffer = image.into_rgba8();
+                let (w, h) = buffer.dimensions();
+                let r = w.min(h) as f32 / 2.0;
+                let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+                for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                    let distance = (x as f32 + 0.5 - cx).hypot(y as f32 + 0.5 - cy);
+                    let coverage = (r - distance + 0.5).clamp(0.0, 1.0);
+                    pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+                }
+                Ok(DynamicImage::ImageRgba8(buffer))
+            }
+            Self::Pixelate { block_size, region } => {
+                if block_size <= 1 {
+                    return Ok(image);
+                }
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                let (w, h) = buffer.dimensions();
+                let (rx, ry, rw, rh) = region.unwrap_or((0, 0, w, h));
+                let (x_end, y_end) = (rx.saturating_add(rw).min(w), ry.saturating_add(rh).min(h));
+                for by in (ry..y_end).step_by(block_size as usize) {
+                    for bx in (rx..x_end).step_by(block_size as usize) {
+                        let xs = bx..bx.saturating_add(block_size).min(x_end);
+                        let ys = by..by.saturating_add(block_size).min(y_end);
+                        let mut sum = [0u64; 4];
+                        for y in ys.clone() {
+                            for x in xs.clone() {
+                                let pixel = buffer.get_pixel(x, y);
+                                (0..4).for_each(|i| sum[i] += pixel[i] as u64);
+                            }
+                        }
+                        let count = (xs.len() * ys.len()) as u64;
+                        let mean = Rgba(sum.map(|s| ((s + count / 2) / count) as u8));
+                        for y in ys.clone() {
+                            for x in xs.clone() {
+                                buffer.put_pixel(x, y, mean);
+                            }
+                        }
+                    }
+                }
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::DenoiseNLM {
+                h,
+                template,
+                search,
+            } => {
+                let alpha = image.color().has_alpha();
+                let buffer = image.into_rgba8();
+                Ok(with_alpha(denoise_nlm(&buffer, h, template, search), alpha))
+            }
+            Self::Vignette { strength, radius } => {
+                let (w, h) = image.dimensions();
+                let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+                let half_diagonal = cx.hypot(cy).max(f32::EPSILON);
+                let radius = radius.clamp(0.0, 1.0);
+                map_image_color_channels(&mut image, |x, y, _, value| {
+                    let distance = (x as f32 + 0.5 - cx).hypot(y as f32 + 0.5 - cy) / half_diagonal;
+                    if distance <= radius {
+                        return value;
+                    }
+                    let t = ((distance - radius) / (1.0 - radius).max(f32::EPSILON)).min(1.0);
+                    value * (1.0 - strength * t * t * (3.0 - 2.0 * t))
+                });
+                Ok(image)
+            }
+            #[cfg(feature = "chrono")]
+            Self::Timestamp {
+                format,
+                font,
+                scale,
+                color,
+                anchor,
+                time,
+            } => {
+                let time = match time {
+                    Some(seconds) => chrono::DateTime::from_timestamp(seconds, 0)
+                        .ok_or(Errors::InvalidTimestamp)?,
+                    None => chrono::Utc::now(),
+                };
+                use std::fmt::Write as _;
+                let mut caption = String::new();
+                write!(caption, "{}", time.format(&format))
+                    .map_err(|_| Errors::InvalidTimestamp)?;
+                draw_text_anchored(
+                    &mut image,
+                    Rgba(color),
+                    &font.get_font()?,
+                    &caption,
+                    scale.to_scale(),
+                    anchor,
+                );
+                Ok(image)
+            }
+            Self::Posterize { levels } => {
+                let table: Vec<u8> = (0..=255).map(|v| posterize(v, levels)).collect();
+                let (w, h) = image.dimensions();
+                for x in 0..w {
+                    for y in 0..h {
+                        let mut pixel = image.get_pixel(x, y);
+                        (0..3).for_each(|i| pixel[i] = table[pixel[i] as usize]);
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+                Ok(image)
+            }
+            Self::Solarize { threshold } => {
+                let table: Vec<u8> = (0..=255u8)
+                    .map(|v| {
+                        if v > threshold || threshold == 0 {
+                            255 - v
+                        } else {
+                            v
+                        }
+                    })
+                    .collect();
+                let (w, h) = image.dimensions();
+                for x in 0..w {
+                    for y in 0..h {
+                        let mut pixel = image.get_pixel(x, y);
+                        (0..3).for_each(|i| pixel[i] = table[pixel[i] as usize]);
+                        image.put_pixel(x, y, pixel);
+                    }
+                }
+                Ok(image)
+            }
+            Self::MirrorHalf { axis, keep } => {
+                let (w, h) = image.dimensions();
+                let (extent, span) = match axis {
+                    Axis::Horizontal => (w, h),
+                    Axis::Vertical => (h, w),
+                };
+                for i in 0..extent / 2 {
+                    let (from, to) = match keep {
+                        Side::Start => (i, extent - 1 - i),
+                        Side::End => (extent - 1 - i, i),
+                    };
+                    for j in 0..span {
+                        let ((fx, fy), (tx, ty)) = match axis {
+                            Axis::Horizontal => ((from, j), (to, j)),
+                            Axis::Vertical => ((j, from), (j, to)),
+                        };
+                        let pixel = image.get_pixel(fx, fy);
+                        image.put_pixel(tx, ty, pixel);
+                    }
+                }
+                Ok(image)
+            }
+            Self::Convolve {
+                kernel,
+                width,
+                height,
+                divisor,
+                bias,
+            } => {
+                if width == 0 || height == 0 || kernel.len() as u64 != width as u64 * height as u64
+                {
+                    return Err(Errors::InvalidKernel);
+                }
+                let divisor = divisor.unwrap_or_else(|| kernel.iter().sum());
+                let divisor = if divisor == 0.0 { 1.0 } else { divisor };
+                let alpha = image.color().has_alpha();
+                let buffer = image.into_rgba8();
+                let mut filtered: RgbaImage = Kernel::new(&kernel, width, height)
+                    .filter(&buffer, |channel, acc: f32| {
+                        *channel = <u8 as Clamp<f32>>::clamp(acc / divisor + bias)
+                    });
+                for (pixel, source) in filtered.pixels_mut().zip(buffer.pixels()) {
+                    pixel[3] = source[3];
+                }
+                Ok(with_alpha(filtered, alpha))
+            }
+            Self::EdgeDetect { normalize } => {
+                let gradients = sobel_gradients(&image.to_luma8());
+                let max = gradients.pixels().map(|p| p[0]).max().unwrap_or(0).max(1) as f32;
+                let edges = GrayImage::from_fn(gradients.width(), gradients.height(), |x, y| {
+                    let magnitude = gradients.get_pixel(x, y)[0];
+                    Luma([if normalize {
+                        (magnitude as f32 * 255.0 / max).round() as u8
+                    } else {
+                        magnitude.min(255) as u8
+                    }])
+                });
+                Ok(DynamicImage::ImageLuma8(edges))
+            }
+            Self::Emboss {
+                strength,
+                per_channel,
+            } => {
+                let alpha = image.color().has_alpha();
+                let kernel = EMBOSS_KERNEL.map(|k| k * strength);
+                let kernel = Kernel::new(&kernel, 3, 3);
+                let emboss = |channel: &mut u8, acc: f32| {
+                    *channel = <u8 as Clamp<f32>>::clamp(acc + 128.0);
+                };
+                Ok(match (per_channel, alpha) {
+                    (false, false) => {
+                        DynamicImage::ImageLuma8(kernel.filter(&image.to_luma8(), emboss))
+                    }
+                    (false, true) => {
+                        let gray: GrayImage = kernel.filter(&image.to_luma8(), emboss);
+                        DynamicImage::ImageLumaA8(ImageBuffer::from_fn(
+                            gray.width(),
+                            gray.height(),
+                            |x, y| LumaA([gray.get_pixel(x, y)[0], 255]),
+                        ))
+                    }
+                    (true, _) => {
+                        let colors: RgbImage = kernel.filter(&image.to_rgb8(), emboss);
+                        let colors = DynamicImage::ImageRgb8(colors);
+                        if alpha {
+                            DynamicImage::ImageRgba8(colors.into_rgba8())
+                        } else {
+                            colors
+                        }
+                    }
+                })
+            }
+            Self::Spherize {
+                center,
+                radius,
+                strength,
+            } => {
+                if radius <= 0.0 {
+                    return Ok(image);
+                }
+                let alpha = image.color().has_alpha();
+                let source = image.into_rgba8();
+                let mut buffer = source.clone();
+                let (cx, cy) = (center.0 as f32, center.1 as f32);
+                let exponent = (1.0 + strength).max(0.1);
+                for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                    let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+                    let distance = dx.hypot(dy);
+                    if distance >= radius || distance == 0.0 {
+                        continue;
+                    }
+                    let scale = (distance / radius).powf(exponent) * radius / distance;
+                    *pixel = sample_bilinear(&source, cx + dx * scale, cy + dy * scale);
+                }
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::MedianBlur { radius } => {
+                if radius == 0 {
+                    return Ok(image);
+                }
+                Ok(match image.color() {
+                    ColorType::L8 => {
+                        DynamicImage::ImageLuma8(median_filter(&image.into_luma8(), radius, radius))
+                    }
+                    ColorType::La8 => DynamicImage::ImageLumaA8(median_filter(
+                        &image.into_luma_alpha8(),
+                        radius,
+                        radius,
+                    )),
+                    color if color.has_alpha() => {
+                        DynamicImage::ImageRgba8(median_filter(&image.into_rgba8(), radius, radius))
+                    }
+                    _ => DynamicImage::ImageRgb8(median_filter(&image.into_rgb8(), radius, radius)),
+                })
+            }
+            Self::StickerOutline { width, color } => {
+                let subject = image.into_rgba8();
+                if width == 0 {
+                    return Ok(DynamicImage::ImageRgba8(subject));
+                }
+                let mask = GrayImage::from_fn(subject.width(), subject.height(), |x, y| {
+                    Luma([if subject.get_pixel(x, y)[3] >= 128 {
+                        255
+                    } else {
+                        0
+                    }])
+                });
+                let distances = euclidean_squared_distance_transform(&mask);
+                let mut canvas = RgbaImage::from_fn(subject.width(), subject.height(), |x, y| {
+                    let distance = distances.get_pixel(x, y)[0].sqrt() as f32;
+                    let coverage = (width as f32 + 0.5 - distance).clamp(0.0, 1.0);
+                    let mut ring = Rgba(color);
+                    ring[3] = (color[3] as f32 * coverage).round() as u8;
+                    ring
+                });
+                imageops::overlay(&mut canvas, &subject, 0, 0);
+                Ok(DynamicImage::ImageRgba8(canvas))
+            }
+            Self::EqualizeHistogram { per_channel } => {
+                let alpha = image.color().has_alpha();
+                let mut buffer = image.into_rgba8();
+                let (w, h) = buffer.dimensions();
+                if per_channel {
+                    for i in 0..3 {
+                        let channel =
+                            GrayImage::from_fn(w, h, |x, y| Luma([buffer.get_pixel(x, y)[i]]));
+                        let channel = equalize_histogram(&channel);
+                        for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                            pixel[i] = channel.get_pixel(x, y)[0];
+                        }
+                    }
+                } else {
+                    let luma = |pixel: &Rgba<u8>| {
+                        0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32
+                    };
+                    let equalized = equalize_histogram(&GrayImage::from_fn(w, h, |x, y| {
+                        Luma([luma(buffer.get_pixel(x, y)).round() as u8])
+                    }));
+                    for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+                        // With Cb and Cr fixed, a change in Y shifts R, G and B equally.
+                        let delta = equalized.get_pixel(x, y)[0] as f32 - luma(pixel);
+                        for channel in pixel.0.iter_mut().take(3) {
+                            *channel = (*channel as f32 + delta).round().clamp(0.0, 255.0) as u8;
+                        }
+                    }
+                }
+                Ok(with_alpha(buffer, alpha))
+            }
+            Self::AutoWhiteBalance => {
+                if image.width() == 0 || image.height() == 0 {
+                    return Ok(image);
+                }
+                let mut sums = [0f64; 3];
+                for pixel in image.to_rgb32f().pixels() {
+                    (0..3).for_each(|i| sums[i] += pixel[i] as f64);
+                }
+                let gray = sums.iter().sum::<f64>() / 3.0;
+                let gains = sums.map(|sum| if sum > 0.0 { (gray / sum) as f32 } else { 1.0 });
+                map_image_color_channels(&mut image, |_, _, i, value| value * gains[i]);
+                Ok(image)
+            }
+            Self::DoG {
+                sigma1,
+                sigma2,
+                threshold,
+            } => {
+                let gray = image.to_luma8();
+                let (w, h) = gray.dimensions();
+                let luminance: GrayF32Image = ImageBuffer::from_fn(w, h, |x, y| {
+                    Luma([gray.get_pixel(x, y)[0] as f32 / 255.0])
+                });
+                let narrow = imageops::blur(&luminance, sigma1.min(sigma2));
+                let wide = imageops::blur(&luminance, sigma1.max(sigma2));
+                let lines = GrayImage::from_fn(w, h, |x, y| {
+                    let difference = narrow.get_pixel(x, y)[0] - wide.get_pixel(x, y)[0];
+                    Luma([if difference < -threshold { 0 } else { 255 }])
+                });
+                Ok(DynamicImage::ImageLuma8(lines))
+            }
+        }
+    }
+}
+
+#[inline]
+pub fn load_file(name: &str) -> Result<Vec<u8>, Errors> {
+    Ok(fs::read(name)?.to_vec())
+}
+
+pub fn load_image_from_file(name: &str) -> Result<DynamicImage, Errors> {
+    let v = load_file(name)?;
+    let c = Cursor::new(v);
+    let img = Reader::new(c).with_guessed_format()?.decode()?;
+    Ok(img)
+}
+
+/// Decodes the `(x, y, w, h)` rectangle of an image file. The rectangle is
+/// checked against the header dimensions before any pixels are decoded. None
+/// of the enabled codecs expose a region decoder, so the whole image is still
+/// decoded and then cropped.
+pub fn decode_region(name: &str, rect: (u32, u32, u32, u32)) -> Result<DynamicImage, Errors> {
+    let (x, y, w, h) = rect;
+    let bytes = load_file(name)?;
+    let (width, height) = Reader::new(Cursor::new(&bytes))
+        .with_guessed_format()?
+        .into_dimensions()?;
+    if w == 0 || h == 0 || x.saturating_add(w) > width || y.saturating_add(h) > height {
+        return Err(Errors::InvalidDimensions);
+    }
+    let image = Reader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .decode()?;
+    Ok(image.crop_imm(x, y, w, h))
+}
+
+pub fn load_image_from_stdin() -> Result<DynamicImage, Errors> {
+    let mut v = Vec::new();
+    io::stdin().lock().read_to_end(&mut v)?;
+    let img = Reader::new(Cursor::new(v))
+        .with_guessed_format()?
+        .decode()?;
+    Ok(img)
+}
+
+pub fn load_font_from_file(name: &str) -> Result<Font<'static>, Errors> {
+    Font::try_from_vec(fs::read(name)?.to_vec()).ok_or(Errors::InvalidFont)
+}
+
+const IDENTICON_GRID: u32 = 5;
+
+pub fn identicon(seed: &str, size: u32) -> RgbImage {
+    let hash = seed.bytes().fold(0xCBF2_9CE4_8422_2325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    });
+    let mut rng = random::Rng::new(hash);
+    let hue = rng.below(360) as f32;
+    let foreground = hsl_to_rgb(hue, 0.65, 0.5);
+    let background = Rgb([240, 240, 240]);
+
+    let half = IDENTICON_GRID.div_ceil(2);
+    let cells: Vec<bool> = (0..IDENTICON_GRID * half)
+        .map(|_| rng.below(2) == 1)
+        .collect();
+
+    RgbImage::from_fn(size, size, |x, y| {
+        let column = x * IDENTICON_GRID / size;
+        let row = y * IDENTICON_GRID / size;
+        let column = column.min(IDENTICON_GRID - 1 - column);
+        if cells[(row * half + column) as usize] {
+            foreground
+        } else {
+            background
+        }
+    })
+}
+
+pub fn rgb_to_hsl(color: Rgb<u8>) -> (f32, f32, f32) {
+    let [r, g, b] = color.0.map(|v| v as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let lightness = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, lightness);
+    }
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (hue, saturation, lightness)
+}
+
+pub fn luminance(color: Rgb<u8>) -> u8 {
+    let [r, g, b] = color.0.map(|v| v as f32);
+    (0.2126 * r + 0.7152 * g + 0.0722 * b).round() as u8
+}
+
+pub fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> Rgb<u8> {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let h = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    Rgb([r, g, b].map(|v| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8))
+}
+
+pub fn fill_color(color: [u8; 3], size: (u32, u32)) -> RgbImage {
+    let mut img = RgbImage::new(size.0, size.1);
+
     for x in 0..size.0 {
         for y in 0..size.1 {
             img.put_pixel(x, y, Rgb(color));
         }
     }
-    img
+    img
+}
+
+fn cap_dimensions(image: DynamicImage, w: u32, h: u32) -> DynamicImage {
+    if image.width() <= w && image.height() <= h {
+        return image;
+    }
+    image.resize(w, h, FilterType::Lanczos3)
+}
+
+pub fn histogram(image: &DynamicImage) -> [[u64; 256]; 4] {
+    let mut histogram = [[0u64; 256]; 4];
+    for (_, _, pixel) in image.pixels() {
+        (0..4).for_each(|i| histogram[i][pixel[i] as usize] += 1);
+    }
+    histogram
+}
+
+fn cumulative_distribution(histogram: &[u64; 256]) -> [f64; 256] {
+    let total = histogram.iter().sum::<u64>().max(1) as f64;
+    let mut cdf = [0.0; 256];
+    let mut running = 0;
+    for (level, count) in histogram.iter().enumerate() {
+        running += count;
+        cdf[level] = running as f64 / total;
+    }
+    cdf
+}
+
+pub fn histogram_match(source: &DynamicImage, reference: &DynamicImage) -> DynamicImage {
+    let source_histogram = histogram(source);
+    let reference_histogram = histogram(reference);
+    let mut mapping = [[0u8; 256]; 3];
+    for channel in 0..3 {
+        let source_cdf = cumulative_distribution(&source_histogram[channel]);
+        let reference_cdf = cumulative_distribution(&reference_histogram[channel]);
+        for level in 0..256 {
+            mapping[channel][level] = reference_cdf
+                .iter()
+                .position(|&v| v >= source_cdf[level])
+                .unwrap_or(255) as u8;
+        }
+    }
+
+    let mut output = source.clone();
+    for (x, y, mut pixel) in source.pixels() {
+        (0..3).for_each(|i| pixel[i] = mapping[i][pixel[i] as usize]);
+        output.put_pixel(x, y, pixel);
+    }
+    output
+}
+
+pub fn clahe(image: &mut RgbaImage, tiles: (u32, u32), clip_limit: f32) {
+    let (w, h) = image.dimensions();
+    if w == 0 || h == 0 {
+        return;
+    }
+    let (tx, ty) = (tiles.0.clamp(1, w), tiles.1.clamp(1, h));
+    let luma = DynamicImage::ImageRgba8(image.clone()).into_luma8();
+
+    let mut mappings = vec![[0u8; 256]; (tx * ty) as usize];
+    for j in 0..ty {
+        for i in 0..tx {
+            let (x0, x1) = (i * w / tx, (i + 1) * w / tx);
+            let (y0, y1) = (j * h / ty, (j + 1) * h / ty);
+            let mut histogram = [0u32; 256];
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    histogram[luma.get_pixel(x, y)[0] as usize] += 1;
+                }
+            }
+            let count = (x1 - x0) * (y1 - y0);
+            let limit = ((clip_limit * count as f32 / 256.0) as u32).max(1);
+            let mut excess = 0;
+            for bin in histogram.iter_mut() {
+                if *bin > limit {
+                    excess += *bin - limit;
+                    *bin = limit;
+                }
+            }
+            let mut cdf = 0;
+            let mapping = &mut mappings[(j * tx + i) as usize];
+            for (level, bin) in histogram.iter().enumerate() {
+                cdf += bin + excess / 256;
+                mapping[level] = (cdf as f32 * 255.0 / count as f32).round().min(255.0) as u8;
+            }
+        }
+    }
+
+    let (tile_w, tile_h) = (w as f32 / tx as f32, h as f32 / ty as f32);
+    let neighbours = |position: f32, count: u32| {
+        let lower = position.floor().clamp(0.0, (count - 1) as f32);
+        let weight = (position - lower).clamp(0.0, 1.0);
+        (lower as u32, (lower as u32 + 1).min(count - 1), weight)
+    };
+    for y in 0..h {
+        let (j0, j1, wy) = neighbours((y as f32 + 0.5) / tile_h - 0.5, ty);
+        for x in 0..w {
+            let (i0, i1, wx) = neighbours((x as f32 + 0.5) / tile_w - 0.5, tx);
+            let value = luma.get_pixel(x, y)[0] as usize;
+            let map = |i: u32, j: u32| mappings[(j * tx + i) as usize][value] as f32;
+            let top = map(i0, j0) * (1.0 - wx) + map(i1, j0) * wx;
+            let bottom = map(i0, j1) * (1.0 - wx) + map(i1, j1) * wx;
+            let equalized = top * (1.0 - wy) + bottom * wy;
+
+            let pixel = image.get_pixel_mut(x, y);
+            if value == 0 {
+                (0..3).for_each(|c| pixel[c] = equalized as u8);
+            } else {
+                let ratio = equalized / value as f32;
+                (0..3).for_each(|c| pixel[c] = (pixel[c] as f32 * ratio).min(255.0) as u8);
+            }
+        }
+    }
+}
+
+pub fn average_color(image: &DynamicImage) -> Rgba<u8> {
+    let mut sums = [0u64; 4];
+    for (_, _, pixel) in image.pixels() {
+        (0..4).for_each(|i| sums[i] += pixel[i] as u64);
+    }
+    let count = (image.width() as u64 * image.height() as u64).max(1);
+    Rgba(sums.map(|sum| (sum / count) as u8))
+}
+
+pub fn stack_blur(image: &mut RgbaImage, radius: u32) {
+    let (w, h) = image.dimensions();
+    if radius == 0 || w == 0 || h == 0 {
+        return;
+    }
+    for y in 0..h {
+        let line: Vec<[u8; 4]> = (0..w).map(|x| image.get_pixel(x, y).0).collect();
+        for (x, pixel) in stack_blur_line(&line, radius).into_iter().enumerate() {
+            image.put_pixel(x as u32, y, Rgba(pixel));
+        }
+    }
+    for x in 0..w {
+        let line: Vec<[u8; 4]> = (0..h).map(|y| image.get_pixel(x, y).0).collect();
+        for (y, pixel) in stack_blur_line(&line, radius).into_iter().enumerate() {
+            image.put_pixel(x, y as u32, Rgba(pixel));
+        }
+    }
+}
+
+fn stack_blur_line(line: &[[u8; 4]], radius: u32) -> Vec<[u8; 4]> {
+    let r = radius as i64;
+    let last = line.len() as i64 - 1;
+    let at = |i: i64| line[i.clamp(0, last) as usize].map(u64::from);
+    let add = |acc: &mut [u64; 4], p: [u64; 4]| (0..4).for_each(|c| acc[c] += p[c]);
+    let sub = |acc: &mut [u64; 4], p: [u64; 4]| (0..4).for_each(|c| acc[c] -= p[c]);
+
+    let (mut sum, mut sum_in, mut sum_out) = ([0u64; 4], [0u64; 4], [0u64; 4]);
+    for i in -r..=r {
+        let p = at(i);
+        (0..4).for_each(|c| sum[c] += p[c] * (r + 1 - i.abs()) as u64);
+        if i <= 0 {
+            add(&mut sum_out, p);
+        } else {
+            add(&mut sum_in, p);
+        }
+    }
+
+    let div = ((r + 1) * (r + 1)) as u64;
+    let mut output = Vec::with_capacity(line.len());
+    for x in 0..=last {
+        output.push(sum.map(|v| (v / div) as u8));
+        sub(&mut sum, sum_out);
+        sub(&mut sum_out, at(x - r));
+        add(&mut sum_in, at(x + r + 1));
+        add(&mut sum, sum_in);
+        sub(&mut sum_in, at(x + 1));
+        add(&mut sum_out, at(x + 1));
+    }
+    output
+}
+
+const STANDARD_LUMINANCE_QUANTIZATION: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+pub fn estimate_jpeg_quality(bytes: &[u8]) -> Option<u8> {
+    if bytes.get(0..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() {
+        if bytes[pos] != 0xFF {
+            return None;
+        }
+        let marker = bytes[pos + 1];
+        match marker {
+            0xFF => {
+                pos += 1;
+                continue;
+            }
+            0x01 | 0xD0..=0xD7 => {
+                pos += 2;
+                continue;
+            }
+            0xD9 | 0xDA => return None,
+            _ => {}
+        }
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let segment = bytes.get(pos + 4..pos + 2 + length)?;
+        if marker == 0xDB {
+            let mut i = 0;
+            while i < segment.len() {
+                let precision = segment[i] >> 4;
+                let table_id = segment[i] & 0x0F;
+                let entry_size = if precision == 0 { 1 } else { 2 };
+                let table = segment.get(i + 1..i + 1 + 64 * entry_size)?;
+                if table_id == 0 {
+                    let sum: u32 = table
+                        .chunks(entry_size)
+                        .map(|v| v.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32))
+                        .sum();
+                    let standard: u32 = STANDARD_LUMINANCE_QUANTIZATION
+                        .iter()
+                        .map(|&v| v as u32)
+                        .sum();
+                    let scale = sum as f32 * 100.0 / standard as f32;
+                    let quality = if scale <= 100.0 {
+                        (200.0 - scale) / 2.0
+                    } else {
+                        5000.0 / scale
+                    };
+                    return Some(quality.round().clamp(1.0, 100.0) as u8);
+                }
+                i += 1 + 64 * entry_size;
+            }
+        }
+        pos += 2 + length;
+    }
+    None
+}
+
+const RED_EYE_MIN_RED: f32 = 80.0;
+const RED_EYE_RATIO: f32 = 1.5;
+const TILT_SHIFT_LEVELS: usize = 4;
+
+pub fn tilt_shift(image: &RgbaImage, focus_y: u32, focus_height: u32, max_sigma: f32) -> RgbaImage {
+    if max_sigma <= 0.0 {
+        return image.clone();
+    }
+    let (w, h) = image.dimensions();
+    let focus_end = focus_y.saturating_add(focus_height).min(h);
+    let mut levels = vec![image.clone()];
+    for level in 1..=TILT_SHIFT_LEVELS {
+        let sigma = max_sigma * level as f32 / TILT_SHIFT_LEVELS as f32;
+        levels.push(imageops::blur(image, sigma));
+    }
+
+    let mut output = RgbaImage::new(w, h);
+    for y in 0..h {
+        let t = if y < focus_y {
+            (focus_y - y) as f32 / focus_y as f32
+        } else if y >= focus_end {
+            (y - focus_end + 1) as f32 / (h - focus_end) as f32
+        } else {
+            0.0
+        };
+        let position = t * TILT_SHIFT_LEVELS as f32;
+        let lower = (position.floor() as usize).min(TILT_SHIFT_LEVELS - 1);
+        let fraction = position - lower as f32;
+        for x in 0..w {
+            let a = levels[lower].get_pixel(x, y);
+            let b = levels[lower + 1].get_pixel(x, y);
+            let pixel = [0, 1, 2, 3]
+                .map(|c| (a[c] as f32 * (1.0 - fraction) + b[c] as f32 * fraction).round() as u8);
+            output.put_pixel(x, y, Rgba(pixel));
+        }
+    }
+    output
+}
+
+pub fn oil_paint(image: &RgbaImage, radius: u32, levels: u8) -> RgbaImage {
+    let (w, h) = image.dimensions();
+    let levels = levels.max(1) as usize;
+    let buckets = RgbaImage::from_fn(w, h, |x, y| {
+        let p = image.get_pixel(x, y);
+        let intensity = (p[0] as usize + p[1] as usize + p[2] as usize) / 3;
+        Rgba([(intensity * levels / 256) as u8, 0, 0, 0])
+    });
+
+    RgbaImage::from_fn(w, h, |x, y| {
+        let mut counts = vec![0u32; levels];
+        let mut sums = vec![[0u64; 4]; levels];
+        let (x0, x1) = (
+            x.saturating_sub(radius),
+            x.saturating_add(radius).min(w - 1),
+        );
+        let (y0, y1) = (
+            y.saturating_sub(radius),
+            y.saturating_add(radius).min(h - 1),
+        );
+        for ny in y0..=y1 {
+            for nx in x0..=x1 {
+                let bucket = buckets.get_pixel(nx, ny)[0] as usize;
+                let p = image.get_pixel(nx, ny);
+                counts[bucket] += 1;
+                (0..4).for_each(|c| sums[bucket][c] += p[c] as u64);
+            }
+        }
+        let (bucket, count) = counts
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, &count)| count)
+            .unwrap();
+        Rgba(sums[bucket].map(|sum| (sum / *count as u64) as u8))
+    })
+}
+
+pub fn halftone(image: &DynamicImage, dot_size: u32, angle: f32, color: Rgba<u8>) -> RgbaImage {
+    let gray = image.to_luma8();
+    let (w, h) = gray.dimensions();
+    let pitch = dot_size.max(1) as f32;
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let mut ink = Rgba([255, 255, 255, 255]);
+    ink.blend(&color);
+
+    RgbaImage::from_fn(w, h, |x, y| {
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        let (u, v) = (px * cos + py * sin, -px * sin + py * cos);
+        let (cu, cv) = (
+            ((u / pitch).floor() + 0.5) * pitch,
+            ((v / pitch).floor() + 0.5) * pitch,
+        );
+        let (cx, cy) = (cu * cos - cv * sin, cu * sin + cv * cos);
+        let sample = gray.get_pixel(
+            (cx.max(0.0) as u32).min(w - 1),
+            (cy.max(0.0) as u32).min(h - 1),
+        )[0];
+        let darkness = 1.0 - sample as f32 / 255.0;
+        let radius = pitch * (darkness / std::f32::consts::PI).sqrt();
+        if (u - cu).hypot(v - cv) <= radius {
+            ink
+        } else {
+            Rgba([255, 255, 255, 255])
+        }
+    })
+}
+
+pub fn round_corners(image: &mut RgbaImage, radius: u32) {
+    let (w, h) = image.dimensions();
+    let radius = radius.min(w / 2).min(h / 2);
+    let r = radius as f32;
+    for y in 0..h {
+        for x in 0..w {
+            let dx = if x < radius {
+                r - x as f32 - 0.5
+            } else if x >= w - radius {
+                x as f32 + 0.5 - (w - radius) as f32
+            } else {
+                continue;
+            };
+            let dy = if y < radius {
+                r - y as f32 - 0.5
+            } else if y >= h - radius {
+                y as f32 + 0.5 - (h - radius) as f32
+            } else {
+                continue;
+            };
+            let coverage = (r - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+            let pixel = image.get_pixel_mut(x, y);
+            pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+        }
+    }
+}
+
+fn bayer_matrix(size: usize) -> Vec<Vec<u32>> {
+    if size <= 1 {
+        return vec![vec![0]];
+    }
+    let half = bayer_matrix(size / 2);
+    let n = size / 2;
+    (0..size)
+        .map(|y| {
+            (0..size)
+                .map(|x| {
+                    let base = 4 * half[y % n][x % n];
+                    base + match (y / n, x / n) {
+                        (0, 0) => 0,
+                        (0, _) => 2,
+                        (_, 0) => 3,
+                        _ => 1,
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+const DOMINANT_COLOR_SAMPLE_SIZE: u32 = 64;
+const DOMINANT_COLOR_ITERATIONS: usize = 10;
+
+pub fn dominant_colors(image: &DynamicImage, k: usize) -> Vec<Rgb<u8>> {
+    let sample = image
+        .thumbnail(DOMINANT_COLOR_SAMPLE_SIZE, DOMINANT_COLOR_SAMPLE_SIZE)
+        .into_rgb8();
+    let pixels: Vec<[f32; 3]> = sample.pixels().map(|p| p.0.map(|v| v as f32)).collect();
+    let k = k.min(pixels.len());
+    if k == 0 {
+        return Vec::new();
+    }
+
+    let mut centroids: Vec<[f32; 3]> = (0..k).map(|i| pixels[i * pixels.len() / k]).collect();
+    let mut assignments = vec![0; pixels.len()];
+    for _ in 0..DOMINANT_COLOR_ITERATIONS {
+        for (pixel, assignment) in pixels.iter().zip(assignments.iter_mut()) {
+            *assignment = nearest_color(&centroids, pixel);
+        }
+        let mut sums = vec![[0.0f32; 3]; k];
+        let mut counts = vec![0usize; k];
+        for (pixel, &assignment) in pixels.iter().zip(assignments.iter()) {
+            (0..3).for_each(|c| sums[assignment][c] += pixel[c]);
+            counts[assignment] += 1;
+        }
+        for ((centroid, sum), &count) in centroids.iter_mut().zip(sums).zip(counts.iter()) {
+            if count > 0 {
+                *centroid = sum.map(|v| v / count as f32);
+            }
+        }
+    }
+
+    let mut counts = vec![0usize; k];
+    assignments.iter().for_each(|&a| counts[a] += 1);
+    let mut clusters: Vec<(usize, [f32; 3])> = counts.into_iter().zip(centroids).collect();
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.0));
+    clusters
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(_, c)| Rgb(c.map(|v| v.round() as u8)))
+        .collect()
+}
+
+fn nearest_color(palette: &[[f32; 3]], color: &[f32; 3]) -> usize {
+    let distance = |p: &[f32; 3]| (0..3).map(|c| (p[c] - color[c]).powi(2)).sum::<f32>();
+    palette
+        .iter()
+        .enumerate()
+        .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+pub fn contact_sheet(
+    images: &[(String, DynamicImage)],
+    cols: usize,
+    cell: (u32, u32),
+    font: &Font,
+    scale: Scale,
+) -> DynamicImage {
+    let cols = cols.max(1);
+    let rows = images.len().div_ceil(cols);
+    let (cell_w, cell_h) = cell;
+    let label_h = (get_font_height(font, scale).ceil() as u32).min(cell_h);
+    let thumb_h = cell_h - label_h;
+    let mut sheet = DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+        cell_w * cols as u32,
+        cell_h * rows as u32,
+        Rgba([255, 255, 255, 255]),
+    ));
+
+    for (index, (name, image)) in images.iter().enumerate() {
+        let x = (index % cols) as u32 * cell_w;
+        let y = (index / cols) as u32 * cell_h;
+        if thumb_h > 0 {
+            let thumb = image.thumbnail(cell_w, thumb_h);
+            imageops::overlay(
+                &mut sheet,
+                &thumb,
+                (x + (cell_w - thumb.width()) / 2).into(),
+                (y + (thumb_h - thumb.height()) / 2).into(),
+            );
+        }
+        let label = truncate_to_width(font, name, scale, cell_w as f32);
+        draw_text(
+            &mut sheet,
+            Rgba([0, 0, 0, 255]),
+            font,
+            &label,
+            scale,
+            &((x + cell_w / 2) as i32, (y + thumb_h) as i32),
+        );
+    }
+    sheet
+}
+
+fn truncate_to_width(font: &Font, text: &str, scale: Scale, max_width: f32) -> String {
+    if measure_line_width(font, text, scale) <= max_width {
+        return text.to_string();
+    }
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let candidate: String = chars.iter().chain(['.', '.', '.'].iter()).collect();
+        if measure_line_width(font, &candidate, scale) <= max_width {
+            return candidate;
+        }
+    }
+    String::new()
+}
+
+fn color_dodge(base: u32, blend: u32) -> u8 {
+    if blend >= 255 {
+        255
+    } else {
+        (base * 255 / (255 - blend)).min(255) as u8
+    }
+}
+
+/// Returns the full mip chain starting with a copy of `image` itself. Each
+/// level halves the previous one, rounding odd sizes down with a minimum of
+/// 1, and the chain stops after the 1×1 level.
+pub fn generate_mipmaps(image: &DynamicImage, filter: FilterType) -> Vec<DynamicImage> {
+    let mut levels = vec![image.clone()];
+    loop {
+        let previous = &levels[levels.len() - 1];
+        let (w, h) = previous.dimensions();
+        if w <= 1 && h <= 1 {
+            break;
+        }
+        let next = previous.resize_exact((w / 2).max(1), (h / 2).max(1), filter);
+        levels.push(next);
+    }
+    levels
+}
+
+pub fn has_transparency(image: &DynamicImage) -> bool {
+    image.color().has_alpha() && image.pixels().any(|(_, _, pixel)| pixel[3] < 255)
+}
+
+/// Counts distinct RGBA values, stopping at `cap`. The result is `cap`
+/// whenever the true count is `cap` or more.
+pub fn unique_color_count(image: &DynamicImage, cap: usize) -> usize {
+    let mut colors = HashSet::new();
+    for (_, _, pixel) in image.pixels() {
+        if colors.len() >= cap {
+            break;
+        }
+        colors.insert(pixel.0);
+    }
+    colors.len()
+}
+
+const GRAPHIC_COLOR_LIMIT: usize = 256;
+
+/// Picks PNG for transparent or flat-colored graphics and JPEG for photos.
+/// Smooth photos get a higher JPEG quality since banding shows up sooner there.
+pub fn recommend_format(image: &DynamicImage) -> ImageOutputFormat {
+    if has_transparency(image) {
+        return ImageOutputFormat::Png;
+    }
+    if unique_color_count(image, GRAPHIC_COLOR_LIMIT + 1) <= GRAPHIC_COLOR_LIMIT {
+        return ImageOutputFormat::Png;
+    }
+    let quality = if sharpness_score(image) < 100.0 {
+        90
+    } else {
+        82
+    };
+    ImageOutputFormat::Jpeg(quality)
+}
+
+pub fn quantize_to_palette(image: &mut RgbaImage, palette: &[Rgb<u8>], dither: bool) {
+    if palette.is_empty() {
+        return;
+    }
+    let palette: Vec<[f32; 3]> = palette.iter().map(|c| c.0.map(|v| v as f32)).collect();
+    let (w, h) = image.dimensions();
+    let mut errors = vec![[0.0f32; 3]; (w * h) as usize];
+    for y in 0..h {
+        for x in 0..w {
+            let index = (y * w + x) as usize;
+            let pixel = image.get_pixel_mut(x, y);
+            let wanted: [f32; 3] =
+                [0, 1, 2].map(|c| (pixel[c] as f32 + errors[index][c]).clamp(0.0, 255.0));
+            let chosen = palette[nearest_color(&palette, &wanted)];
+            (0..3).for_each(|c| pixel[c] = chosen[c] as u8);
+            if !dither {
+                continue;
+            }
+            let error = [0, 1, 2].map(|c| wanted[c] - chosen[c]);
+            let mut spread = |dx: i64, dy: i64, weight: f32| {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx >= 0 && nx < w as i64 && ny < h as i64 {
+                    let target = &mut errors[(ny * w as i64 + nx) as usize];
+                    (0..3).for_each(|c| target[c] += error[c] * weight);
+                }
+            };
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+}
+
+pub fn sharpness_score(image: &DynamicImage) -> f64 {
+    let gray = image.to_luma8();
+    let (w, h) = gray.dimensions();
+    if w < 3 || h < 3 {
+        return 0.0;
+    }
+    let at = |x: u32, y: u32| gray.get_pixel(x, y)[0] as f64;
+    let responses: Vec<f64> = (1..h - 1)
+        .flat_map(|y| (1..w - 1).map(move |x| (x, y)))
+        .map(|(x, y)| at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y))
+        .collect();
+    let mean = responses.iter().sum::<f64>() / responses.len() as f64;
+    responses.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / responses.len() as f64
+}
+
+const SEAMLESS_CLONE_ITERATIONS: usize = 300;
+const SEAMLESS_CLONE_RELAXATION: f32 = 1.8;
+
+/// Simplified Poisson blend: inside `mask`, solves for pixels whose gradients
+/// match `source` while the mask boundary takes its values from `image`.
+pub fn seamless_clone(
+    image: &mut RgbaImage,
+    source: &RgbImage,
+    mask: &GrayImage,
+    coords: (i64, i64),
+) {
+    let (w, h) = (image.width() as i64, image.height() as i64);
+    let (sw, sh) = (source.width() as i64, source.height() as i64);
+    let inside = |sx: i64, sy: i64| {
+        let (x, y) = (sx + coords.0, sy + coords.1);
+        sx >= 0
+            && sy >= 0
+            && sx < sw
+            && sy < sh
+            && x > 0
+            && y > 0
+            && x < w - 1
+            && y < h - 1
+            && mask.get_pixel(sx as u32, sy as u32)[0] > 127
+    };
+    let masked: Vec<(i64, i64)> = (0..sh)
+        .flat_map(|y| (0..sw).map(move |x| (x, y)))
+        .filter(|&(x, y)| inside(x, y))
+        .collect();
+    let index = |x: i64, y: i64| (y * sw + x) as usize;
+    let src = |x: i64, y: i64| source.get_pixel(x as u32, y as u32).0.map(|v| v as f32);
+
+    let mut values: Vec<[f32; 3]> = (0..sh)
+        .flat_map(|y| (0..sw).map(move |x| (x, y)))
+        .map(|(x, y)| src(x, y))
+        .collect();
+    let neighbours = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+    for _ in 0..SEAMLESS_CLONE_ITERATIONS {
+        for &(x, y) in masked.iter() {
+            let centre = src(x, y);
+            let mut total = [0.0f32; 3];
+            for (dx, dy) in neighbours {
+                let (nx, ny) = (x + dx, y + dy);
+                let neighbour = if inside(nx, ny) {
+                    values[index(nx, ny)]
+                } else {
+                    let p = image.get_pixel((nx + coords.0) as u32, (ny + coords.1) as u32);
+                    [p[0], p[1], p[2]].map(|v| v as f32)
+                };
+                let guide = if nx >= 0 && ny >= 0 && nx < sw && ny < sh {
+                    src(nx, ny)
+                } else {
+                    centre
+                };
+                (0..3).for_each(|c| total[c] += neighbour[c] + centre[c] - guide[c]);
+            }
+            let current = &mut values[index(x, y)];
+            (0..3).for_each(|c| {
+                current[c] += SEAMLESS_CLONE_RELAXATION * (total[c] / 4.0 - current[c])
+            });
+        }
+    }
+
+    for &(x, y) in masked.iter() {
+        let pixel = image.get_pixel_mut((x + coords.0) as u32, (y + coords.1) as u32);
+        let value = values[index(x, y)];
+        (0..3).for_each(|c| pixel[c] = value[c].round().clamp(0.0, 255.0) as u8);
+    }
+}
+
+const AUTO_ENHANCE_CLIP: f32 = 0.005;
+const AUTO_ENHANCE_SATURATION: f32 = 1.1;
+const AUTO_ENHANCE_SHARPEN_SIGMA: f32 = 0.8;
+const AUTO_ENHANCE_SHARPEN_THRESHOLD: i32 = 3;
+
+pub fn auto_contrast(image: &mut RgbaImage, clip: f32) {
+    let mut histogram = [0u64; 256];
+    image
+        .pixels()
+        .for_each(|p| histogram[luminance(p.to_rgb()) as usize] += 1);
+    let total: u64 = histogram.iter().sum();
+    let cutoff = (total as f32 * clip) as u64;
+
+    let mut running = 0;
+    let low = histogram
+        .iter()
+        .position(|&count| {
+            running += count;
+            running > cutoff
+        })
+        .unwrap_or(0);
+    running = 0;
+    let high = 255
+        - histogram
+            .iter()
+            .rev()
+            .position(|&count| {
+                running += count;
+                running > cutoff
+            })
+            .unwrap_or(0);
+    if high <= low {
+        return;
+    }
+
+    let range = (high - low) as f32;
+    image.pixels_mut().for_each(|p| {
+        (0..3).for_each(|i| {
+            p[i] = ((p[i] as f32 - low as f32) * 255.0 / range)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+    });
+}
+
+pub fn adjust_saturation(image: &mut RgbaImage, factor: f32) {
+    image.pixels_mut().for_each(|p| {
+        let gray = luminance(p.to_rgb()) as f32;
+        (0..3).for_each(|i| {
+            p[i] = (gray + (p[i] as f32 - gray) * factor)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        });
+    });
+}
+
+/// Returns one grayscale image per channel, ordered `[R, G, B]`, or
+/// `[R, G, B, A]` when the source has an alpha channel.
+pub fn split_channels(image: &DynamicImage) -> Vec<DynamicImage> {
+    let count = if image.color().has_alpha() { 4 } else { 3 };
+    let buffer = image.to_rgba8();
+    (0..count)
+        .map(|channel| {
+            DynamicImage::ImageLuma8(GrayImage::from_fn(
+                buffer.width(),
+                buffer.height(),
+                |x, y| Luma([buffer.get_pixel(x, y)[channel]]),
+            ))
+        })
+        .collect()
+}
+
+pub fn merge_channels(channels: Vec<DynamicImage>) -> Result<DynamicImage, Errors> {
+    if !(3..=4).contains(&channels.len()) {
+        return Err(Errors::InvalidDimensions);
+    }
+    let size = channels[0].dimensions();
+    if channels.iter().any(|c| c.dimensions() != size) {
+        return Err(Errors::InvalidDimensions);
+    }
+    let channels: Vec<GrayImage> = channels.into_iter().map(|c| c.into_luma8()).collect();
+    let merged = RgbaImage::from_fn(size.0, size.1, |x, y| {
+        let value = |i: usize| channels.get(i).map_or(255, |c| c.get_pixel(x, y)[0]);
+        Rgba([value(0), value(1), value(2), value(3)])
+    });
+    Ok(with_alpha(merged, channels.len() == 4))
+}
+
+/// Rebuilds `target` from `tiles` on a `grid` of columns × rows. Each cell is
+/// replaced by the tile whose average colour is nearest (Euclidean distance in
+/// RGB) to the cell's average colour, cropped to fill the cell. An empty
+/// `tiles` slice returns a copy of `target`.
+pub fn photomosaic(
+    target: &DynamicImage,
+    tiles: &[DynamicImage],
+    grid: (u32, u32),
+) -> DynamicImage {
+    if tiles.is_empty() {
+        return target.clone();
+    }
+    let (w, h) = target.dimensions();
+    let (cols, rows) = (grid.0.clamp(1, w.max(1)), grid.1.clamp(1, h.max(1)));
+    let averages: Vec<[f32; 3]> = tiles
+        .iter()
+        .map(|tile| average_color(tile).to_rgb().0.map(|v| v as f32))
+        .collect();
+
+    let mut output = DynamicImage::new_rgba8(w, h);
+    for row in 0..rows {
+        for col in 0..cols {
+            let (x0, x1) = (col * w / cols, (col + 1) * w / cols);
+            let (y0, y1) = (row * h / rows, (row + 1) * h / rows);
+            if x1 == x0 || y1 == y0 {
+                continue;
+            }
+            let cell = target.crop_imm(x0, y0, x1 - x0, y1 - y0);
+            let color = average_color(&cell).to_rgb().0.map(|v| v as f32);
+            let tile = &tiles[nearest_color(&averages, &color)];
+            let piece = tile.resize_to_fill(x1 - x0, y1 - y0, FilterType::Triangle);
+            imageops::replace(&mut output, &piece, x0.into(), y0.into());
+        }
+    }
+    output
+}
+
+const CONFETTI_COLORS: [[u8; 4]; 6] = [
+    [231, 76, 60, 255],
+    [241, 196, 15, 255],
+    [46, 204, 113, 255],
+    [52, 152, 219, 255],
+    [155, 89, 182, 255],
+    [230, 126, 34, 255],
+];
+
+pub fn draw_particles(
+    image: &mut DynamicImage,
+    kind: ParticleKind,
+    count: u32,
+    seed: u64,
+    frame: u32,
+) {
+    let (w, h) = image.dimensions();
+    if w == 0 || h == 0 {
+        return;
+    }
+    let mut rng = random::Rng::new(seed);
+    for _ in 0..count {
+        let start = (rng.next_f32() * w as f32, rng.next_f32() * h as f32);
+        let drift = rng.next_f32() - 0.5;
+        let size = 1 + rng.below(3);
+        let (speed, color) = match kind {
+            ParticleKind::Snow => (1.0 + rng.next_f32(), [255, 255, 255, 255]),
+            ParticleKind::Confetti => (
+                2.0 + 2.0 * rng.next_f32(),
+                CONFETTI_COLORS[rng.below(CONFETTI_COLORS.len() as u32) as usize],
+            ),
+        };
+        let x = (start.0 + drift * frame as f32).rem_euclid(w as f32) as i32;
+        let y = (start.1 + speed * frame as f32).rem_euclid(h as f32) as i32;
+        match kind {
+            ParticleKind::Snow => draw_filled_circle_mut(image, (x, y), size as i32, Rgba(color)),
+            ParticleKind::Confetti => {
+                draw_filled_rect_mut(image, Rect::at(x, y).of_size(size * 2, size), Rgba(color))
+            }
+        }
+    }
+}
+
+const SSIM_SIGMA: f32 = 1.5;
+
+type GrayF32Image = ImageBuffer<Luma<f32>, Vec<f32>>;
+
+pub fn ssim(a: &DynamicImage, b: &DynamicImage) -> Result<f64, Errors> {
+    if a.dimensions() != b.dimensions() {
+        return Err(Errors::InvalidDimensions);
+    }
+    let (w, h) = a.dimensions();
+    if w == 0 || h == 0 {
+        return Ok(1.0);
+    }
+    let to_f32 = |image: &DynamicImage| {
+        let gray = image.to_luma8();
+        ImageBuffer::from_fn(w, h, |x, y| Luma([gray.get_pixel(x, y)[0] as f32 / 255.0]))
+    };
+    let (x, y): (GrayF32Image, GrayF32Image) = (to_f32(a), to_f32(b));
+    let product = |p: &GrayF32Image, q: &GrayF32Image| {
+        ImageBuffer::from_fn(w, h, |i, j| {
+            Luma([p.get_pixel(i, j)[0] * q.get_pixel(i, j)[0]])
+        })
+    };
+    let mu_x = imageops::blur(&x, SSIM_SIGMA);
+    let mu_y = imageops::blur(&y, SSIM_SIGMA);
+    let xx = imageops::blur(&product(&x, &x), SSIM_SIGMA);
+    let yy = imageops::blur(&product(&y, &y), SSIM_SIGMA);
+    let xy = imageops::blur(&product(&x, &y), SSIM_SIGMA);
+
+    let c1 = 0.01f64.powi(2);
+    let c2 = 0.03f64.powi(2);
+    let mut total = 0.0;
+    for j in 0..h {
+        for i in 0..w {
+            let (mx, my) = (
+                mu_x.get_pixel(i, j)[0] as f64,
+                mu_y.get_pixel(i, j)[0] as f64,
+            );
+            let vx = xx.get_pixel(i, j)[0] as f64 - mx * mx;
+            let vy = yy.get_pixel(i, j)[0] as f64 - my * my;
+            let cov = xy.get_pixel(i, j)[0] as f64 - mx * my;
+            total += ((2.0 * mx * my + c1) * (2.0 * cov + c2))
+                / ((mx * mx + my * my + c1) * (vx + vy + c2));
+        }
+    }
+    Ok(total / (w as f64 * h as f64))
+}
+
+/// Rotates `image` clockwise by `degrees` onto a canvas just large enough to
+/// hold it, filling the uncovered corners with `background`. Multiples of 90°
+/// are exact, and empty images are returned unchanged.
+pub fn rotate_expand(image: &RgbaImage, degrees: f32, background: Rgba<u8>) -> RgbaImage {
+    if let Some(quarters) = quarter_turns(degrees) {
+        return match quarters {
+            1 => imageops::rotate90(image),
+            2 => imageops::rotate180(image),
+            3 => imageops::rotate270(image),
+            _ => image.clone(),
+        };
+    }
+    if image.width() == 0 || image.height() == 0 {
+        return image.clone();
+    }
+    let theta = degrees.rem_euclid(360.0).to_radians();
+    let (w, h) = (image.width() as f32, image.height() as f32);
+    let (sin, cos) = (theta.sin().abs(), theta.cos().abs());
+    let new_w = ((w * cos + h * sin).round() as u32).max(1);
+    let new_h = ((w * sin + h * cos).round() as u32).max(1);
+
+    let projection = Projection::translate(new_w as f32 / 2.0, new_h as f32 / 2.0)
+        * Projection::rotate(theta)
+        * Projection::translate(-w / 2.0, -h / 2.0);
+    let mut canvas = RgbaImage::new(new_w, new_h);
+    warp_into(
+        image,
+        &projection,
+        Interpolation::Bilinear,
+        background,
+        &mut canvas,
+    );
+    canvas
+}
+
+/// Returns the number of clockwise quarter turns when `degrees` is a
+/// multiple of 90°.
+fn quarter_turns(degrees: f32) -> Option<u32> {
+    let quarters = degrees.rem_euclid(360.0) / 90.0;
+    ((quarters - quarters.round()).abs() < 1e-4).then(|| quarters.round() as u32 % 4)
+}
+
+fn inscribed_rect_size((w, h): (u32, u32), degrees: f32) -> (u32, u32) {
+    let theta = degrees.rem_euclid(360.0).to_radians();
+    let (sin, cos) = (theta.sin().abs(), theta.cos().abs());
+    let (w, h) = (w as f32, h as f32);
+    let (long, short) = if w >= h { (w, h) } else { (h, w) };
+    let (rect_w, rect_h) = if short <= 2.0 * sin * cos * long || (sin - cos).abs() < 1e-6 {
+        let x = 0.5 * short;
+        if w >= h {
+            (x / sin, x / cos)
+        } else {
+            (x / cos, x / sin)
+        }
+    } else {
+        let cos_2a = cos * cos - sin * sin;
+        ((w * cos - h * sin) / cos_2a, (h * cos - w * sin) / cos_2a)
+    };
+    (
+        (rect_w.floor() as u32).max(1),
+        (rect_h.floor() as u32).max(1),
+    )
+}
+
+/// Collapses `image` into a `width`×1 strip. Each output pixel is the mean of
+/// every source pixel in its share of the columns, so colours are averaged
+/// top-to-bottom and the strip reads left-to-right like the source.
+pub fn color_summary_strip(image: &DynamicImage, width: u32) -> DynamicImage {
+    let (w, h) = image.dimensions();
+    let width = width.max(1);
+    let mut sums = vec![[0u64; 4]; width as usize];
+    let mut counts = vec![0u64; width as usize];
+    for (x, _, pixel) in image.pixels() {
+        let bucket = (x as u64 * width as u64 / w as u64) as usize;
+        (0..4).for_each(|i| sums[bucket][i] += pixel[i] as u64);
+        counts[bucket] += 1;
+    }
+    let strip = RgbaImage::from_fn(width, 1, |x, _| {
+        let count = counts[x as usize];
+        if count == 0 {
+            // More output columns than source columns: reuse the nearest one.
+            let source = (x as u64 * w as u64 / width as u64) as u32;
+            let column = image.crop_imm(source, 0, 1, h);
+            return average_color(&column);
+        }
+        Rgba(sums[x as usize].map(|sum| (sum / count) as u8))
+    });
+    DynamicImage::ImageRgba8(strip)
+}
+
+const EDGE_SHARPEN_SIGMA: f32 = 1.0;
+const SOBEL_MAX_MAGNITUDE: f32 = 1442.5;
+const SOBEL_MAX_RESPONSE: f32 = 1020.0;
+const EMBOSS_KERNEL: [f32; 9] = [-2.0, -1.0, 0.0, -1.0, 0.0, 1.0, 0.0, 1.0, 2.0];
+
+fn color_blend(samples: &mut [u8], channels: usize, width: u32, color: [u8; 3]) {
+    let blend_row = |row: &mut [u8]| {
+        row.chunks_mut(channels).for_each(|pixel| {
+            (0..3).for_each(|i| {
+                pixel[i] = pixel[i] / 2 + color[i] / 2;
+            })
+        })
+    };
+    let row_len = (width as usize * channels).max(1);
+    #[cfg(feature = "rayon")]
+    samples.par_chunks_mut(row_len).for_each(blend_row);
+    #[cfg(not(feature = "rayon"))]
+    samples.chunks_mut(row_len).for_each(blend_row);
+}
+
+pub fn to_color_type(image: DynamicImage, type_: &str) -> Result<DynamicImage, Errors> {
+    match type_ {
+        "rgb8" => Ok(DynamicImage::ImageRgb8(image.into_rgb8())),
+        "rgba8" => Ok(DynamicImage::ImageRgba8(image.into_rgba8())),
+        "luma8" => Ok(DynamicImage::ImageLuma8(image.into_luma8())),
+        "luma_alpha8" => Ok(DynamicImage::ImageLumaA8(image.into_luma_alpha8())),
+        "rgb16" => Ok(DynamicImage::ImageRgb16(image.into_rgb16())),
+        "rgba16" => Ok(DynamicImage::ImageRgba16(image.into_rgba16())),
+        "luma16" => Ok(DynamicImage::ImageLuma16(image.into_luma16())),
+        "luma_alpha16" => Ok(DynamicImage::ImageLumaA16(image.into_luma_alpha16())),
+        "rgb32f" => Ok(DynamicImage::ImageRgb32F(image.into_rgb32f())),
+        "rgba32f" => Ok(DynamicImage::ImageRgba32F(image.into_rgba32f())),
+        _ => Err(Errors::InvalidImageType),
+    }
+}
+
+const GRID_LABEL_SCALE: f32 = 12.0;
+
+pub fn blend_layer(
+    image: &mut DynamicImage,
+    layer: &DynamicImage,
+    coords: (i64, i64),
+    mode: BlendMode,
+) {
+    let (w, h) = (image.width() as i64, image.height() as i64);
+    let x0 = coords.0.max(0);
+    let y0 = coords.1.max(0);
+    let x1 = (coords.0 + layer.width() as i64).min(w);
+    let y1 = (coords.1 + layer.height() as i64).min(h);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let top = layer.get_pixel((x - coords.0) as u32, (y - coords.1) as u32);
+            let mut pixel = image.get_pixel(x as u32, y as u32);
+            let alpha = top[3] as f32 / 255.0;
+            let base = [0, 1, 2].map(|i| pixel[i] as f32 / 255.0);
+            let blended = mode.blend(base, [0, 1, 2].map(|i| top[i] as f32 / 255.0));
+            (0..3).for_each(|i| {
+                let value = base[i] + (blended[i] - base[i]) * alpha;
+                pixel[i] = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+            });
+            image.put_pixel(x as u32, y as u32, pixel);
+        }
+    }
+}
+
+/// Thick lines are rasterized into a mask by distance to the segment so a
+/// translucent color is blended exactly once per covered pixel.
+fn draw_thick_line(
+    image: &mut RgbaImage,
+    from: (f32, f32),
+    to: (f32, f32),
+    color: Rgba<u8>,
+    thickness: u32,
+) {
+    let (width, height) = image.dimensions();
+    let mut mask = GrayImage::new(width, height);
+    if thickness == 1 {
+        draw_line_segment_mut(&mut mask, from, to, Luma([255]));
+    } else {
+        let half = thickness as f32 / 2.0;
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let length_sq = (dx * dx + dy * dy).max(f32::EPSILON);
+        let clamp_x = |v: f32| (v.max(0.0) as u32).min(width);
+        let clamp_y = |v: f32| (v.max(0.0) as u32).min(height);
+        let (x0, x1) = (
+            clamp_x(from.0.min(to.0) - half),
+            clamp_x(from.0.max(to.0) + half + 1.0),
+        );
+        let (y0, y1) = (
+            clamp_y(from.1.min(to.1) - half),
+            clamp_y(from.1.max(to.1) + half + 1.0),
+        );
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let t = (((px - from.0) * dx + (py - from.1) * dy) / length_sq).clamp(0.0, 1.0);
+                let (cx, cy) = (from.0 + t * dx, from.1 + t * dy);
+                if (px - cx).powi(2) + (py - cy).powi(2) <= half * half {
+                    mask.put_pixel(x, y, Luma([255]));
+                }
+            }
+        }
+    }
+    blend_mask(image, &mask, color);
+}
+
+/// Blends `color` once over every pixel set in `mask`.
+fn blend_mask(image: &mut RgbaImage, mask: &GrayImage, color: Rgba<u8>) {
+    for (pixel, coverage) in image.pixels_mut().zip(mask.pixels()) {
+        if coverage[0] > 0 {
+            pixel.blend(&color);
+        }
+    }
+}
+
+/// Snaps `value` to the nearest of `levels` evenly spaced steps in `0..=255`.
+fn posterize(value: u8, levels: u8) -> u8 {
+    let steps = levels.max(2) as f32 - 1.0;
+    ((value as f32 / 255.0 * steps).round() / steps * 255.0).round() as u8
+}
+
+pub fn denoise_nlm(image: &RgbaImage, h: f32, template: u32, search: u32) -> RgbaImage {
+    let (w, ht) = image.dimensions();
+    let mut out = image.clone();
+    if w == 0 || ht == 0 || h <= 0.0 {
+        return out;
+    }
+    let (t, s) = (template as i64, search as i64);
+    let at = |x: i64, y: i64| {
+        image.get_pixel(
+            x.clamp(0, w as i64 - 1) as u32,
+            y.clamp(0, ht as i64 - 1) as u32,
+        )
+    };
+    let patch_area = ((2 * t + 1) * (2 * t + 1) * 3) as f32;
+    let h2 = h * h;
+    let denoise_row = |(y, row): (usize, &mut [u8])| {
+        let y = y as i64;
+        for (x, pixel) in row.chunks_mut(4).enumerate() {
+            let x = x as i64;
+            let mut sum = [0f32; 3];
+            let mut total = 0f32;
+            for sy in y - s..=y + s {
+                for sx in x - s..=x + s {
+                    let mut distance = 0f32;
+                    for dy in -t..=t {
+                        for dx in -t..=t {
+                            let (a, b) = (at(x + dx, y + dy), at(sx + dx, sy + dy));
+                            distance += (0..3)
+                                .map(|i| (a[i] as f32 - b[i] as f32).powi(2))
+                                .sum::<f32>();
+                        }
+                    }
+                    let weight = (-distance / patch_area / h2).exp();
+                    let sample = at(sx, sy);
+                    (0..3).for_each(|i| sum[i] += weight * sample[i] as f32);
+                    total += weight;
+                }
+            }
+            (0..3).for_each(|i| pixel[i] = (sum[i] / total).round().clamp(0.0, 255.0) as u8);
+        }
+    };
+    let row_len = w as usize * 4;
+    #[cfg(feature = "rayon")]
+    out.par_chunks_mut(row_len)
+        .enumerate()
+        .for_each(denoise_row);
+    #[cfg(not(feature = "rayon"))]
+    out.chunks_mut(row_len).enumerate().for_each(denoise_row);
+    out
+}
+
+/// Bilinear sample with coordinates clamped to the image edges.
+fn sample_bilinear(image: &RgbaImage, x: f32, y: f32) -> Rgba<u8> {
+    let (w, h) = image.dimensions();
+    let x = x.clamp(0.0, w.saturating_sub(1) as f32);
+    let y = y.clamp(0.0, h.saturating_sub(1) as f32);
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+    let (a, b, c, d) = (
+        image.get_pixel(x0, y0),
+        image.get_pixel(x1, y0),
+        image.get_pixel(x0, y1),
+        image.get_pixel(x1, y1),
+    );
+    Rgba([0, 1, 2, 3].map(|i| {
+        let top = a[i] as f32 * (1.0 - fx) + b[i] as f32 * fx;
+        let bottom = c[i] as f32 * (1.0 - fx) + d[i] as f32 * fx;
+        (top * (1.0 - fy) + bottom * fy).round() as u8
+    }))
+}
+
+/// Maps every color channel through `f(x, y, channel, value)` in the image's
+/// own subpixel type, so no variant is requantized. Integer results are
+/// rounded; alpha is untouched.
+fn map_image_color_channels(image: &mut DynamicImage, f: impl Fn(u32, u32, usize, f32) -> f32) {
+    match image {
+        DynamicImage::ImageLuma8(buffer) => map_color_channels(buffer, &f),
+        DynamicImage::ImageLumaA8(buffer) => map_color_channels(buffer, &f),
+        DynamicImage::ImageRgb8(buffer) => map_color_channels(buffer, &f),
+        DynamicImage::ImageRgba8(buffer) => map_color_channels(buffer, &f),
+        DynamicImage::ImageLuma16(buffer) => map_color_channels(buffer, &f),
+        DynamicImage::ImageLumaA16(buffer) => map_color_channels(buffer, &f),
+        DynamicImage::ImageRgb16(buffer) => map_color_channels(buffer, &f),
+        DynamicImage::ImageRgba16(buffer) => map_color_channels(buffer, &f),
+        DynamicImage::ImageRgb32F(buffer) => map_color_channels(buffer, &f),
+        DynamicImage::ImageRgba32F(buffer) => map_color_channels(buffer, &f),
+        other => {
+            let mut buffer = other.to_rgba32f();
+            map_color_channels(&mut buffer, &f);
+            *other = DynamicImage::ImageRgba32F(buffer);
+        }
+    }
+}
+
+fn map_color_channels<P>(
+    buffer: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    f: impl Fn(u32, u32, usize, f32) -> f32,
+) where
+    P: Pixel,
+    P::Subpixel: ValueInto<f32> + Clamp<f32>,
+{
+    // Luma, LumaA, Rgb and Rgba all store alpha, when present, last.
+    let colors = if P::CHANNEL_COUNT % 2 == 0 {
+        P::CHANNEL_COUNT - 1
+    } else {
+        P::CHANNEL_COUNT
+    } as usize;
+    let max: f32 = <P::Subpixel as image::Primitive>::DEFAULT_MAX_VALUE
+        .value_into()
+        .unwrap_or(1.0);
+    let integral = max > 1.0;
+    for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+        for (i, channel) in pixel.channels_mut().iter_mut().take(colors).enumerate() {
+            let value: f32 = (*channel).value_into().unwrap_or(0.0);
+            let mapped = f(x, y, i, value);
+            *channel = Clamp::clamp(if integral { mapped.round() } else { mapped });
+        }
+    }
+}
+
+/// Places `image` at `offset` on a `size` canvas filled with `fill`.
+fn pad_buffer<P: Pixel + 'static>(
+    image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    (width, height): (u32, u32),
+    (left, top): (u32, u32),
+    fill: P,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let mut canvas = ImageBuffer::from_pixel(width, height, fill);
+    imageops::replace(&mut canvas, image, left as i64, top as i64);
+    canvas
+}
+
+fn with_alpha(image: RgbaImage, alpha: bool) -> DynamicImage {
+    if alpha {
+        DynamicImage::ImageRgba8(image)
+    } else {
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(image).into_rgb8())
+    }
 }
 
-fn filter_from_str(filter: String) -> Result<FilterType, Errors> {
-    match filter.as_str() {
-        "Nearest" => Ok(FilterType::Nearest),
-        "Triangle" => Ok(FilterType::Triangle),
-        "CatmullRom" => Ok(FilterType::CatmullRom),
-        "Gaussian" => Ok(FilterType::Gaussian),
-        "Lanczos3" => Ok(FilterType::Lanczos3),
-        _ => Err(Errors::InvalidResizeFilter),
+pub fn nine_patch(
+    patch: &DynamicImage,
+    insets: (u32, u32, u32, u32),
+    target: (u32, u32),
+) -> Result<DynamicImage, Errors> {
+    let (left, top, right, bottom) = insets;
+    let (pw, ph) = patch.dimensions();
+    let (tw, th) = target;
+    let horizontal = left.checked_add(right).ok_or(Errors::InvalidNinePatch)?;
+    let vertical = top.checked_add(bottom).ok_or(Errors::InvalidNinePatch)?;
+    if horizontal > pw.min(tw) || vertical > ph.min(th) {
+        return Err(Errors::InvalidNinePatch);
+    }
+
+    let src_x = [0, left, pw - right, pw];
+    let src_y = [0, top, ph - bottom, ph];
+    let dst_x = [0, left, tw - right, tw];
+    let dst_y = [0, top, th - bottom, th];
+
+    let mut output = DynamicImage::new_rgba8(tw, th);
+    for i in 0..3 {
+        for j in 0..3 {
+            let (dw, dh) = (dst_x[i + 1] - dst_x[i], dst_y[j + 1] - dst_y[j]);
+            let (sw, sh) = (src_x[i + 1] - src_x[i], src_y[j + 1] - src_y[j]);
+            if dw == 0 || dh == 0 || sw == 0 || sh == 0 {
+                continue;
+            }
+            let cell = patch.crop_imm(src_x[i], src_y[j], sw, sh).resize_exact(
+                dw,
+                dh,
+                FilterType::Triangle,
+            );
+            imageops::replace(&mut output, &cell, dst_x[i].into(), dst_y[j].into());
+        }
     }
+    Ok(output)
 }
 
 fn get_font_height(font: &Font, scale: Scale) -> f32 {
@@ -412,8 +4416,8 @@ fn get_font_height(font: &Font, scale: Scale) -> f32 {
     v_metrics.ascent - v_metrics.descent + v_metrics.line_gap
 }
 
-pub fn draw_text<'a, C>(
-    image: &'a mut C,
+pub fn draw_text<C>(
+    image: &mut C,
     color: C::Pixel,
     font: &Font,
     fulltext: &str,
@@ -435,12 +4439,36 @@ pub fn draw_text<'a, C>(
         let text_width = measure_line_width(font, text, scale);
         let x = *raw_x - (text_width as i32) / 2;
         let y_delta = ((index as f32 - (line_count - 1) as f32 / 2f32) * text_height) as i32;
-        let y = (*raw_y as i32 + y_delta) as i32;
+        let y = *raw_y + y_delta;
 
         draw_text_mut(image, color, x, y, scale, font, text);
     }
 }
 
+/// Draws a single line of text positioned by `anchor` within the image.
+pub fn draw_text_anchored(
+    image: &mut DynamicImage,
+    color: Rgba<u8>,
+    font: &Font,
+    text: &str,
+    scale: Scale,
+    anchor: Anchor,
+) {
+    let size = (
+        measure_line_width(font, text, scale).ceil() as u32,
+        get_font_height(font, scale).ceil() as u32,
+    );
+    let (x, y) = anchor.position(image.dimensions(), size);
+    draw_text(
+        image,
+        color,
+        font,
+        text,
+        scale,
+        &((x + size.0 as i64 / 2) as i32, y as i32),
+    );
+}
+
 pub fn measure_line_width(font: &Font, text: &str, scale: Scale) -> f32 {
     font.layout(text, scale, point(0.0, 0.0))
         .map(|g| g.position().x + g.unpositioned().h_metrics().advance_width)
@@ -454,3 +4482,427 @@ pub fn image_to_bytes(image: DynamicImage, format: ImageOutputFormat) -> Result<
     image.write_to(&mut w, format)?;
     Ok(bytes)
 }
+
+/// Encodes WebP with `quality` in `0..=100`, or losslessly when `None`.
+/// Returns `Errors::InvalidOutputFormat` unless the `webp` feature is enabled.
+pub fn image_to_webp(image: DynamicImage, quality: Option<u8>) -> Result<Vec<u8>, Errors> {
+    #[cfg(feature = "webp")]
+    {
+        let (w, h) = image.dimensions();
+        let encoded = if image.color().has_alpha() {
+            let buffer = image.into_rgba8();
+            webp::Encoder::from_rgba(&buffer, w, h)
+                .encode_simple(quality.is_none(), quality.unwrap_or(100).min(100) as f32)
+        } else {
+            let buffer = image.into_rgb8();
+            webp::Encoder::from_rgb(&buffer, w, h)
+                .encode_simple(quality.is_none(), quality.unwrap_or(100).min(100) as f32)
+        };
+        Ok(encoded.map_err(Errors::WebpEncodeError)?.to_vec())
+    }
+    #[cfg(not(feature = "webp"))]
+    {
+        let _ = (image, quality);
+        Err(Errors::InvalidOutputFormat)
+    }
+}
+
+pub fn write_image_to_stdout(image: DynamicImage, format: ImageOutputFormat) -> Result<(), Errors> {
+    let bytes = image_to_bytes(image, format)?;
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&bytes)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+pub fn image_to_bytes_with_quality(
+    image: DynamicImage,
+    format_name: &str,
+    quality: u8,
+) -> Result<Vec<u8>, Errors> {
+    let format = match format_name.to_ascii_lowercase().as_str() {
+        "jpeg" | "jpg" => ImageOutputFormat::Jpeg(quality.clamp(1, 100)),
+        "png" => ImageOutputFormat::Png,
+        "gif" => ImageOutputFormat::Gif,
+        "webp" => return image_to_webp(image, Some(quality)),
+        _ => return Err(Errors::InvalidOutputFormat),
+    };
+    image_to_bytes(image, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, 1, |x, _| {
+            let v = (x * 255 / (width - 1)) as u8;
+            Rgb([v, v, v])
+        }))
+    }
+
+    #[test]
+    fn sepia_matches_reference_bytes() {
+        let input = RgbImage::from_vec(2, 2, vec![0, 0, 50, 255, 255, 255, 100, 50, 0, 10, 20, 30])
+            .unwrap();
+        let out = ImageOperation::Sepia
+            .apply(DynamicImage::ImageRgb8(input))
+            .unwrap()
+            .into_rgb8();
+        assert_eq!(
+            out.into_raw(),
+            vec![9, 8, 7, 255, 255, 239, 78, 69, 54, 25, 22, 17]
+        );
+    }
+
+    fn color_blend_reference(mut image: DynamicImage, color: [u8; 3]) -> DynamicImage {
+        let (w, h) = image.dimensions();
+        for x in 0..w {
+            for y in 0..h {
+                let mut pixel = image.get_pixel(x, y);
+                (0..3).for_each(|i| pixel[i] = pixel[i] / 2 + color[i] / 2);
+                image.put_pixel(x, y, pixel);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn color_blend_matches_per_pixel_loop() {
+        let color = [200, 31, 77];
+        let rgba = RgbaImage::from_fn(1021, 769, |x, y| {
+            Rgba([(x * 7) as u8, (y * 13) as u8, (x ^ y) as u8, (x + y) as u8])
+        });
+        let inputs = [
+            DynamicImage::ImageRgba8(rgba.clone()),
+            DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).into_rgb8()),
+        ];
+        for input in inputs {
+            let expected = color_blend_reference(input.clone(), color);
+            let actual = ImageOperation::ColorBlend {
+                r: color[0],
+                g: color[1],
+                b: color[2],
+            }
+            .apply(input)
+            .unwrap();
+            assert_eq!(actual.as_bytes(), expected.as_bytes());
+        }
+    }
+
+    #[test]
+    fn game_boy_palette_maps_gradient_to_four_colors() {
+        let palette = PaletteName::GameBoy.colors();
+        let out = ImageOperation::NamedPalette {
+            palette: PaletteName::GameBoy,
+            dither: false,
+        }
+        .apply(gradient(256))
+        .unwrap()
+        .into_rgb8();
+        assert!(out.pixels().all(|pixel| palette.contains(pixel)));
+        for color in &palette {
+            assert!(out.pixels().any(|pixel| pixel == color));
+        }
+    }
+
+    #[test]
+    fn zero_saturation_matches_grayscale() {
+        let input = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            Rgb([(x * 4) as u8, (y * 4) as u8, (x * y) as u8])
+        }));
+        let desaturated = ImageOperation::AdjustSaturation(0.0)
+            .apply(input.clone())
+            .unwrap()
+            .into_rgb8();
+        let gray = ImageOperation::Grayscale.apply(input).unwrap().into_luma8();
+        for (pixel, luma) in desaturated.pixels().zip(gray.pixels()) {
+            assert!(pixel.0.iter().all(|&v| v.abs_diff(luma[0]) <= 1));
+        }
+    }
+
+    fn solid(w: u32, h: u32, color: [u8; 4]) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(w, h, Rgba(color)))
+    }
+
+    #[test]
+    fn multiply_blend_matches_hand_computed_values() {
+        let mut image = solid(2, 1, [200, 100, 50, 255]);
+        let layer = DynamicImage::ImageRgba8(
+            RgbaImage::from_vec(2, 1, vec![128, 255, 0, 255, 128, 255, 0, 128]).unwrap(),
+        );
+        blend_layer(&mut image, &layer, (0, 0), BlendMode::Multiply);
+        let out = image.into_rgba8();
+        assert_eq!(out.get_pixel(0, 0).0, [100, 100, 0, 255]);
+        assert_eq!(out.get_pixel(1, 0).0, [150, 100, 25, 255]);
+    }
+
+    #[test]
+    fn blend_layer_clips_at_the_edges() {
+        let base = [200, 200, 200, 255];
+        let mut image = solid(4, 4, base);
+        blend_layer(
+            &mut image,
+            &solid(3, 3, [0, 0, 0, 255]),
+            (2, 2),
+            BlendMode::Multiply,
+        );
+        let out = image.into_rgba8();
+        for (x, y, pixel) in out.enumerate_pixels() {
+            let expected = if x >= 2 && y >= 2 {
+                [0, 0, 0, 255]
+            } else {
+                base
+            };
+            assert_eq!(pixel.0, expected, "pixel ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn blend_layer_handles_negative_coordinates() {
+        let base = [255, 255, 255, 255];
+        let mut image = solid(3, 3, base);
+        let layer = DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 3, |x, y| {
+            Rgba([(x * 10 + y) as u8, 0, 0, 255])
+        }));
+        blend_layer(&mut image, &layer, (-2, -1), BlendMode::Multiply);
+        let out = image.into_rgba8();
+        assert_eq!(out.get_pixel(0, 0).0, [21, 0, 0, 255]);
+        assert_eq!(out.get_pixel(0, 1).0, [22, 0, 0, 255]);
+        assert_eq!(out.get_pixel(1, 0).0, base);
+        assert_eq!(out.get_pixel(0, 2).0, base);
+    }
+
+    #[test]
+    fn pad_keeps_luma_and_16_bit_formats() {
+        let pad = |image, color| {
+            ImageOperation::Pad {
+                top: 1,
+                right: 2,
+                bottom: 3,
+                left: 4,
+                color,
+            }
+            .apply(image)
+            .unwrap()
+        };
+
+        let luma = DynamicImage::ImageLuma8(GrayImage::from_pixel(2, 2, Luma([7])));
+        let padded = pad(luma.clone(), [9, 9, 9, 255]).into_luma8();
+        assert_eq!(padded.dimensions(), (8, 6));
+        assert_eq!(padded.get_pixel(0, 0), &Luma([9]));
+        assert_eq!(padded.get_pixel(4, 1), &Luma([7]));
+
+        let translucent = pad(luma.clone(), [9, 9, 9, 128]);
+        assert_eq!(translucent.color(), ColorType::La8);
+        assert_eq!(
+            translucent.into_luma_alpha8().get_pixel(0, 0),
+            &LumaA([9, 128])
+        );
+
+        assert_eq!(pad(luma, [255, 0, 0, 255]).color(), ColorType::Rgb8);
+
+        let rgb16 = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(2, 2, Rgb([1000u16; 3])));
+        let padded = pad(rgb16, [255, 128, 0, 255]).into_rgb16();
+        assert_eq!(padded.get_pixel(0, 0), &Rgb([65535, 32896, 0]));
+        assert_eq!(padded.get_pixel(4, 1), &Rgb([1000; 3]));
+    }
+
+    #[test]
+    fn pad_rejects_overflowing_dimensions() {
+        let result = ImageOperation::Pad {
+            top: 0,
+            right: u32::MAX,
+            bottom: 0,
+            left: 1,
+            color: [0, 0, 0, 255],
+        }
+        .apply(DynamicImage::new_rgb8(1, 1));
+        assert!(matches!(result, Err(Errors::InvalidDimensions)));
+    }
+
+    #[test]
+    fn solarize_extreme_thresholds() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(256, 1, |x, _| {
+            let v = x as u8;
+            Rgba([v, 255 - v, v / 2, v])
+        }));
+        let solarize = |threshold| {
+            ImageOperation::Solarize { threshold }
+                .apply(image.clone())
+                .unwrap()
+        };
+        assert_eq!(solarize(255).as_bytes(), image.as_bytes());
+        let inverted = ImageOperation::Invert.apply(image.clone()).unwrap();
+        assert_eq!(solarize(0).as_bytes(), inverted.as_bytes());
+    }
+
+    #[test]
+    fn hsl_blend_modes_of_blue_over_red() {
+        let blend = |mode| {
+            let mut image = solid(1, 1, [255, 0, 0, 255]);
+            blend_layer(&mut image, &solid(1, 1, [0, 0, 255, 255]), (0, 0), mode);
+            image.into_rgba8().get_pixel(0, 0).0
+        };
+        assert_eq!(blend(BlendMode::Hue), [54, 54, 255, 255]);
+        assert_eq!(blend(BlendMode::Saturation), [255, 0, 0, 255]);
+        assert_eq!(blend(BlendMode::Color), [54, 54, 255, 255]);
+        assert_eq!(blend(BlendMode::Luminosity), [94, 0, 0, 255]);
+    }
+
+    #[test]
+    fn median_blur_keeps_grayscale() {
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(5, 5, |x, y| {
+            Luma([if (x, y) == (2, 2) { 255 } else { 10 }])
+        }));
+        let out = ImageOperation::MedianBlur { radius: 1 }
+            .apply(image)
+            .unwrap();
+        assert_eq!(out.color(), ColorType::L8);
+        assert_eq!(out.into_luma8().get_pixel(2, 2), &Luma([10]));
+    }
+
+    #[test]
+    fn auto_white_balance_equalizes_channel_means() {
+        let tinted = DynamicImage::ImageRgb8(RgbImage::from_fn(16, 16, |x, y| {
+            Rgb([
+                (40 + x * 4) as u8,
+                (50 + y * 4) as u8,
+                (120 + (x + y) * 2) as u8,
+            ])
+        }));
+        let out = ImageOperation::AutoWhiteBalance
+            .apply(tinted)
+            .unwrap()
+            .into_rgb8();
+        let mut means = [0f64; 3];
+        for pixel in out.pixels() {
+            (0..3).for_each(|i| means[i] += pixel[i] as f64 / 256.0);
+        }
+        assert!((means[0] - 100.0).abs() < 1.0, "{means:?}");
+        assert!((means[1] - 100.0).abs() < 1.0, "{means:?}");
+        assert!((means[2] - 100.0).abs() < 1.0, "{means:?}");
+    }
+
+    #[test]
+    fn rotate_angle_keeps_the_whole_image() {
+        let rotate = |degrees, w, h| {
+            ImageOperation::RotateAngle {
+                degrees,
+                background: [0, 0, 0, 0],
+            }
+            .apply(solid(w, h, [10, 20, 30, 255]))
+            .unwrap()
+            .into_rgba8()
+        };
+        let covered = |image: &RgbaImage| image.pixels().filter(|p| p[3] >= 128).count();
+
+        let quarter = rotate(90.0, 200, 100);
+        assert_eq!(quarter.dimensions(), (100, 200));
+        assert_eq!(covered(&quarter), 20_000);
+
+        let tilted = rotate(80.0, 200, 100);
+        assert!(
+            covered(&tilted).abs_diff(20_000) < 600,
+            "{}",
+            covered(&tilted)
+        );
+
+        assert_eq!(rotate(30.0, 0, 0).dimensions(), (0, 0));
+    }
+
+    #[test]
+    fn rotate_crop_leaves_no_background() {
+        for degrees in [0.0, 7.5, 30.0, 45.0, 90.0, 135.0, 200.0] {
+            let out = ImageOperation::RotateCrop { degrees }
+                .apply(solid(200, 100, [10, 20, 30, 255]))
+                .unwrap()
+                .into_rgba8();
+            assert!(
+                out.pixels().all(|p| p[3] == 255),
+                "{degrees}° left transparent pixels"
+            );
+        }
+    }
+
+    #[test]
+    fn vignette_keeps_16_bit_precision() {
+        let image = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(4, 4, Rgb([1000u16; 3])));
+        let out = ImageOperation::Vignette {
+            strength: 0.0,
+            radius: 0.5,
+        }
+        .apply(image.clone())
+        .unwrap();
+        assert_eq!(out.color(), ColorType::Rgb16);
+        assert_eq!(out.as_bytes(), image.as_bytes());
+    }
+
+    #[test]
+    fn pixelate_huge_block_in_offset_region() {
+        let out = ImageOperation::Pixelate {
+            block_size: u32::MAX,
+            region: Some((2, 2, 4, 4)),
+        }
+        .apply(DynamicImage::ImageRgb8(RgbImage::from_fn(8, 8, |x, y| {
+            Rgb([(x * 10) as u8, (y * 10) as u8, 0])
+        })))
+        .unwrap()
+        .into_rgb8();
+        assert_eq!(out.get_pixel(2, 2), out.get_pixel(5, 5));
+        assert_eq!(out.get_pixel(0, 0), &Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn fit_aspect_rejects_empty_images() {
+        let result = ImageOperation::FitAspect {
+            ratio_w: 16,
+            ratio_h: 9,
+            pad_color: [0, 0, 0, 255],
+            prefer: Prefer::Auto,
+        }
+        .apply(DynamicImage::new_rgb8(0, 0));
+        assert!(matches!(result, Err(Errors::InvalidDimensions)));
+    }
+
+    #[test]
+    fn scaled_grid_keeps_line_count() {
+        let scaled = ImageOperation::Grid {
+            spacing: 100,
+            color: [255, 0, 0, 255],
+            labels: false,
+            font: None,
+        }
+        .scaled(0.25);
+        assert!(matches!(scaled, ImageOperation::Grid { spacing: 25, .. }));
+        let grid = scaled
+            .apply(solid(100, 100, [0, 0, 0, 255]))
+            .unwrap()
+            .into_rgba8();
+        let lines = (0..100).filter(|&x| grid.get_pixel(x, 1)[0] == 255).count();
+        assert_eq!(lines, 4);
+    }
+
+    #[test]
+    fn auto_white_balance_handles_empty_and_16_bit_images() {
+        let empty = ImageOperation::AutoWhiteBalance
+            .apply(DynamicImage::new_rgb8(0, 5))
+            .unwrap();
+        assert_eq!(empty.dimensions(), (0, 5));
+
+        let image = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(2, 2, Rgb([1000u16; 3])));
+        let out = ImageOperation::AutoWhiteBalance
+            .apply(image.clone())
+            .unwrap();
+        assert_eq!(out.color(), ColorType::Rgb16);
+        assert_eq!(out.as_bytes(), image.as_bytes());
+    }
+
+    #[test]
+    fn cylindrical_project_passes_empty_images_through() {
+        let out = ImageOperation::CylindricalProject { fov: 90.0 }
+            .apply(DynamicImage::new_rgb8(0, 4))
+            .unwrap();
+        assert_eq!(out.dimensions(), (0, 4));
+    }
+}
@@ -0,0 +1,60 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Once,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+static STATE: AtomicU64 = AtomicU64::new(0);
+/// Completed by whichever comes first: `set_global_seed`, or the clock
+/// seeding in `Rng::from_global`, so the clock never overwrites a chosen seed.
+static SEED_INIT: Once = Once::new();
+
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+pub fn set_global_seed(seed: u64) {
+    SEED_INIT.call_once(|| {});
+    STATE.store(seed, Ordering::SeqCst);
+}
+
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Draws the next generator from the global stream, so every randomized
+    /// operation in a pipeline is reproducible from one `set_global_seed` call.
+    pub fn from_global() -> Self {
+        SEED_INIT.call_once(|| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or_default();
+            STATE.store(now, Ordering::SeqCst);
+        });
+        let state = STATE.fetch_add(GOLDEN_GAMMA, Ordering::SeqCst);
+        Self::new(mix(state.wrapping_add(GOLDEN_GAMMA)))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(GOLDEN_GAMMA);
+        mix(self.0)
+    }
+
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn below(&mut self, bound: u32) -> u32 {
+        (((self.next_u64() >> 32) * bound as u64) >> 32) as u32
+    }
+}
+
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}